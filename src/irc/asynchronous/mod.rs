@@ -0,0 +1,285 @@
+use std::{
+    future::poll_fn,
+    io::Result as IOResult,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Instant,
+};
+
+use futures_core::Stream;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    time::{sleep, timeout},
+};
+
+use super::{
+    check_text, checked_channel,
+    parser::Parser,
+    ratelimit::{Limited, RateLimiter},
+    reader::LineReader,
+    Command, Irc, IrcError, IrcType, LocoConfig, IRC_PORT,
+};
+
+const CHUNK_SIZE: usize = 1024;
+
+/// Asynchronous connection on tokio, events are read as a `Stream` and commands
+/// are sent with async methods. Server PINGs are answered while reading,
+/// keepalive PINGs and reconnection are only done by `LocoConnection`.
+/// A command over the rate limit waits for capacity, up to 30 seconds, and the
+/// stream is not read meanwhile so PINGs are not answered, do not await many
+/// commands in a row while the stream has to be read
+pub struct AsyncLocoConnection {
+    read_half: OwnedReadHalf,
+    write_half: OwnedWriteHalf,
+    config: LocoConfig,
+    limiter: RateLimiter,
+    reader: LineReader,
+    /// Automatic replies waiting to be written while reading
+    pending: Vec<u8>,
+}
+
+impl AsyncLocoConnection {
+    /// Initialize a Tcp Connection
+    pub async fn new(loco_config: LocoConfig) -> Result<AsyncLocoConnection, IrcError> {
+        loco_config.validate().map_err(IrcError::Config)?;
+        let address = (
+            loco_config.host.as_str(),
+            loco_config.port.unwrap_or(IRC_PORT),
+        );
+        let connection = match loco_config.connect_timeout {
+            Some(duration) => timeout(duration, TcpStream::connect(address))
+                .await
+                .map_err(|_| IrcError::Timeout)??,
+            None => TcpStream::connect(address).await?,
+        };
+        let (read_half, write_half) = connection.into_split();
+        let mut con = AsyncLocoConnection {
+            read_half,
+            write_half,
+            limiter: RateLimiter::new(loco_config.rate_limit),
+            config: loco_config,
+            reader: LineReader::default(),
+            pending: Vec::new(),
+        };
+        con.write(&con.config.login_commands().concat()).await?;
+        for channel in con.config.channels.clone() {
+            con.join(&channel).await?;
+        }
+        Ok(con)
+    }
+
+    /// Wait for capacity in the rate limiter and write the command, after the rest
+    /// of the automatic replies so the lines are not mixed
+    async fn write(&mut self, command: &str) -> Result<(), IrcError> {
+        if let Some(limited) = Limited::of(command) {
+            while let Err(wait) = self.limiter.acquire(&limited, Instant::now()) {
+                sleep(wait).await;
+            }
+        }
+        let write_timeout = self.config.write_timeout;
+        let write = async {
+            poll_fn(|cx| self.poll_pending(cx)).await?;
+            self.write_half.write_all(command.as_bytes()).await
+        };
+        match write_timeout {
+            Some(duration) => timeout(duration, write)
+                .await
+                .map_err(|_| IrcError::Timeout)??,
+            None => write.await?,
+        }
+        Ok(())
+    }
+
+    /// Send a command to IRC, chat messages fail with `IrcError::Anonymous`
+    /// in anonymous connections, `arg` can not have line breaks.
+    /// It waits for the rate limiter without reading the stream
+    pub async fn send_command(&mut self, command: Command, arg: &str) -> Result<(), IrcError> {
+        check_text(arg)?;
+        let arg = match command {
+            Command::Join | Command::Part => checked_channel(arg)?,
+            Command::Privmsg => {
+                self.check_can_chat()?;
+                arg.into()
+            }
+            _ => arg.into(),
+        };
+        let command = command.build(arg, &self.config);
+        self.write(&command).await
+    }
+
+    /// Join a channel
+    pub async fn join(&mut self, channel: &str) -> Result<(), IrcError> {
+        self.send_command(Command::Join, &checked_channel(channel)?)
+            .await
+    }
+
+    /// Leave a channel
+    pub async fn part(&mut self, channel: &str) -> Result<(), IrcError> {
+        self.send_command(Command::Part, &checked_channel(channel)?)
+            .await
+    }
+
+    /// Send a chat message to a channel, the text can not have line breaks.
+    /// It waits for the rate limiter without reading the stream
+    pub async fn privmsg(&mut self, channel: &str, text: &str) -> Result<(), IrcError> {
+        self.check_can_chat()?;
+        let channel = checked_channel(channel)?;
+        check_text(text)?;
+        let command = format!("PRIVMSG #{channel} :{text}\r\n");
+        self.write(&command).await
+    }
+
+    /// Send QUIT and close the writing side, the stream ends when the server closes
+    pub async fn shutdown(&mut self) -> Result<(), IrcError> {
+        self.send_command(Command::Quit, "").await?;
+        self.write_half.shutdown().await?;
+        Ok(())
+    }
+
+    fn check_can_chat(&self) -> Result<(), IrcError> {
+        if self.config.anonymous {
+            return Err(IrcError::Anonymous);
+        }
+        Ok(())
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        while !self.pending.is_empty() {
+            let written = ready!(Pin::new(&mut self.write_half).poll_write(cx, &self.pending))?;
+            self.pending.drain(..written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Stream for AsyncLocoConnection {
+    type Item = Result<Irc, IrcError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Poll::Ready(Err(err)) = this.poll_pending(cx) {
+            return Poll::Ready(Some(Err(err.into())));
+        }
+        loop {
+            let line = match this.reader.take_line() {
+                Some(line) => line,
+                None => {
+                    let mut chunk = [0; CHUNK_SIZE];
+                    let mut buf = ReadBuf::new(&mut chunk);
+                    ready!(Pin::new(&mut this.read_half).poll_read(cx, &mut buf))?;
+                    if !buf.filled().is_empty() {
+                        if let Err(err) = this.reader.push(buf.filled()) {
+                            return Poll::Ready(Some(Err(IrcError::Parse {
+                                line: String::new(),
+                                reason: err.to_string(),
+                            })));
+                        }
+                        continue;
+                    }
+                    match this.reader.take_rest() {
+                        Some(line) => line,
+                        None => return Poll::Ready(None),
+                    }
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let irc = String::from_utf8(line)
+                .map_err(IrcError::InvalidUtf8)
+                .and_then(|msg| Parser.parse(msg));
+            if let Ok(Irc {
+                irc_type: IrcType::Ping,
+                ..
+            }) = irc
+            {
+                let pong = Command::Pong.build(String::new(), &this.config);
+                this.pending.extend_from_slice(pong.as_bytes());
+                if let Poll::Ready(Err(err)) = this.poll_pending(cx) {
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            }
+            return Poll::Ready(Some(irc));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    async fn next(conn: &mut AsyncLocoConnection) -> Option<Result<Irc, IrcError>> {
+        poll_fn(|cx| Pin::new(&mut *conn).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn read_and_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = LocoConfig::new("token".into(), "nick".into(), "foo".into())
+            .with_host("127.0.0.1".into())
+            .with_port(port);
+        let (conn, server) = tokio::join!(AsyncLocoConnection::new(config), listener.accept());
+        let mut conn = conn.unwrap();
+        let (mut server, _) = server.unwrap();
+        server
+            .write_all(b"PING :tmi.twitch.tv\r\n:bar!bar@bar.tmi.twitch.tv PRIVMSG #foo :hi\r\n")
+            .await
+            .unwrap();
+
+        let irc = next(&mut conn).await.unwrap().unwrap();
+        assert!(matches!(irc.irc_type, IrcType::Ping));
+        let irc = next(&mut conn).await.unwrap().unwrap();
+        assert_eq!(Some("hi".into()), irc.message);
+        conn.privmsg("#foo", "hello").await.unwrap();
+        conn.shutdown().await.unwrap();
+
+        let mut lines = BufReader::new(server).lines();
+        let mut received = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            received.push(line);
+        }
+        assert_eq!("PASS oauth:token", received[3]);
+        assert!(received.contains(&"JOIN #foo".to_string()));
+        assert_eq!(
+            ["PONG :tmi.twitch.tv", "PRIVMSG #foo :hello", "QUIT"],
+            received[received.len() - 3..]
+        );
+    }
+
+    #[tokio::test]
+    async fn pending_reply_before_commands() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = LocoConfig::new("token".into(), "nick".into(), "foo".into())
+            .with_host("127.0.0.1".into())
+            .with_port(port);
+        let (conn, server) = tokio::join!(AsyncLocoConnection::new(config), listener.accept());
+        let mut conn = conn.unwrap();
+        let (server, _) = server.unwrap();
+        // rest of a PONG partially written while reading
+        conn.pending.extend_from_slice(b"twitch.tv\r\n");
+        conn.privmsg("foo", "hello").await.unwrap();
+        conn.shutdown().await.unwrap();
+
+        let mut lines = BufReader::new(server).lines();
+        let mut received = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            received.push(line);
+        }
+        assert!(conn.pending.is_empty());
+        assert_eq!(
+            ["twitch.tv", "PRIVMSG #foo :hello", "QUIT"],
+            received[received.len() - 3..]
+        );
+    }
+}
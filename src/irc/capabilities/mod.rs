@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+/// Twitch IRC capabilities requested when connecting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Twitch commands like CLEARCHAT, USERNOTICE and RECONNECT
+    Commands,
+    /// JOIN and PART of the other users
+    Membership,
+    /// IRCv3 tags with badges, colors, emotes and ids
+    Tags,
+}
+
+impl Capability {
+    /// Name used in the CAP commands
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Commands => "twitch.tv/commands",
+            Self::Membership => "twitch.tv/membership",
+            Self::Tags => "twitch.tv/tags",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "twitch.tv/commands" => Some(Self::Commands),
+            "twitch.tv/membership" => Some(Self::Membership),
+            "twitch.tv/tags" => Some(Self::Tags),
+            _ => None,
+        }
+    }
+}
+
+/// Server answer to a requested capability
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityStatus {
+    /// Requested, still without answer
+    Requested,
+    /// Enabled by a CAP ACK
+    Acknowledged,
+    /// Refused by a CAP NAK
+    Rejected,
+}
+
+/// Capabilities requested in the current connection and their status
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    status: BTreeMap<Capability, CapabilityStatus>,
+}
+
+impl Capabilities {
+    pub(super) fn requested(capabilities: &[Capability]) -> Self {
+        Self {
+            status: capabilities
+                .iter()
+                .map(|capability| (*capability, CapabilityStatus::Requested))
+                .collect(),
+        }
+    }
+
+    /// Status of a capability, `None` when it was not requested
+    pub fn status(&self, capability: Capability) -> Option<CapabilityStatus> {
+        self.status.get(&capability).copied()
+    }
+
+    /// The server acknowledged the capability, like `Capability::Tags` to receive tags
+    pub fn is_enabled(&self, capability: Capability) -> bool {
+        self.status(capability) == Some(CapabilityStatus::Acknowledged)
+    }
+
+    /// Iterate over the requested capabilities and their status
+    pub fn iter(&self) -> impl Iterator<Item = (Capability, CapabilityStatus)> + '_ {
+        self.status
+            .iter()
+            .map(|(capability, status)| (*capability, *status))
+    }
+
+    /// Update with the space separated capabilities of a CAP ACK or NAK
+    pub(super) fn answer(&mut self, names: &str, status: CapabilityStatus) {
+        for capability in names.split(' ').filter_map(Capability::parse) {
+            self.status.insert(capability, status);
+        }
+    }
+}
+
+/// A `CAP REQ` for each capability, the server acknowledges or rejects a request
+/// as a whole so a rejected capability does not disable the others
+pub(super) fn request(capabilities: &[Capability]) -> Vec<String> {
+    capabilities
+        .iter()
+        .map(|capability| format!("CAP REQ :{}\r\n", capability.as_str()))
+        .collect()
+}
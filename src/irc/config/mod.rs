@@ -0,0 +1,605 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{ErrorKind, Result as IOResult},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+#[cfg(feature = "tls")]
+use super::CertificateDer;
+use super::{
+    capabilities, random, Capability, Command, FullQueue, IrcError, RateLimitProfile,
+    ReconnectPolicy, DEFAULT_LOGIN_TIMEOUT, DEFAULT_PONG_TIMEOUT, DEFAULT_QUEUE_CAPACITY, IRC_URL,
+    POLL_INTERVAL,
+};
+
+const MAX_NAME_LEN: usize = 25;
+
+/// Configuration of authentication in IRC Twitch Chat
+#[derive(Clone)]
+pub struct LocoConfig {
+    pub(super) oauth: String,
+    pub(super) nickname: String,
+    pub(super) channels: Vec<String>,
+    pub(super) anonymous: bool,
+    pub(super) capabilities: Vec<Capability>,
+    pub(super) ping_interval: Option<Duration>,
+    pub(super) pong_timeout: Duration,
+    pub(super) reconnect: ReconnectPolicy,
+    pub(super) rate_limit: RateLimitProfile,
+    pub(super) queue_capacity: usize,
+    pub(super) full_queue: FullQueue,
+    pub(super) host: String,
+    pub(super) port: Option<u16>,
+    pub(super) connect_timeout: Option<Duration>,
+    pub(super) login_timeout: Duration,
+    pub(super) read_timeout: Option<Duration>,
+    pub(super) write_timeout: Option<Duration>,
+    #[cfg(feature = "tls")]
+    pub(super) root_certificates: Vec<CertificateDer<'static>>,
+}
+
+/// Builds a `LocoConfig` normalizing the credentials: the `oauth:` prefix is
+/// removed, nickname and channels are lowercased and channels lose the `#`
+#[derive(Clone)]
+pub struct LocoConfigBuilder {
+    oauth: Option<String>,
+    nickname: Option<String>,
+    channels: Vec<String>,
+    anonymous: bool,
+    config: LocoConfig,
+}
+
+/// Invalid value in `LocoConfigBuilder`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    MissingOAuth,
+    /// The token is not printed to not leak it in logs
+    InvalidOAuth,
+    MissingNickname,
+    InvalidNickname(String),
+    InvalidChannel(String),
+    /// The outgoing queue needs space for at least one message
+    InvalidQueueCapacity,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::MissingOAuth => write!(f, "missing oauth token"),
+            Self::InvalidOAuth => write!(f, "oauth token must contain only letters and numbers"),
+            Self::MissingNickname => write!(f, "missing nickname"),
+            Self::InvalidNickname(nickname) => write!(
+                f,
+                "invalid nickname `{nickname}`, use up to {MAX_NAME_LEN} letters, numbers or underscores"
+            ),
+            Self::InvalidChannel(channel) => write!(
+                f,
+                "invalid channel `{channel}`, use up to {MAX_NAME_LEN} letters, numbers or underscores"
+            ),
+            Self::InvalidQueueCapacity => write!(f, "queue capacity must be at least 1"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl LocoConfig {
+    /// Returns a Config Object
+    pub fn new(oauth: String, nickname: String, channel_to_join: String) -> Self {
+        Self {
+            oauth: oauth_token(&oauth),
+            nickname: nickname.trim().to_lowercase(),
+            ..Self::empty()
+        }
+        .with_channels([channel_to_join])
+    }
+
+    /// Read only config logged as a random `justinfan` user
+    pub fn anonymous(channel_to_join: String) -> Self {
+        Self {
+            nickname: anonymous_nickname(),
+            anonymous: true,
+            ..Self::empty()
+        }
+        .with_channels([channel_to_join])
+    }
+
+    /// Logged without credentials, sending chat messages is not allowed
+    pub fn is_anonymous(&self) -> bool {
+        self.anonymous
+    }
+
+    /// Validated config, see `LocoConfigBuilder`
+    pub fn builder() -> LocoConfigBuilder {
+        LocoConfigBuilder::default()
+    }
+
+    fn empty() -> Self {
+        Self {
+            oauth: String::new(),
+            nickname: String::new(),
+            channels: Vec::new(),
+            anonymous: false,
+            capabilities: vec![
+                Capability::Commands,
+                Capability::Membership,
+                Capability::Tags,
+            ],
+            ping_interval: None,
+            pong_timeout: DEFAULT_PONG_TIMEOUT,
+            reconnect: ReconnectPolicy::default(),
+            rate_limit: RateLimitProfile::default(),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            full_queue: FullQueue::default(),
+            host: IRC_URL.into(),
+            port: None,
+            connect_timeout: None,
+            login_timeout: DEFAULT_LOGIN_TIMEOUT,
+            read_timeout: None,
+            write_timeout: None,
+            #[cfg(feature = "tls")]
+            root_certificates: Vec::new(),
+        }
+    }
+
+    /// Also join these channels when connecting
+    pub fn with_channels<I, S>(mut self, channels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for channel in channels {
+            let channel = channel_name(channel.as_ref());
+            if !self.channels.contains(&channel) {
+                self.channels.push(channel);
+            }
+        }
+        self
+    }
+
+    /// Channel used by `Command::Privmsg`, the first one configured
+    pub(super) fn default_channel(&self) -> &str {
+        self.channels
+            .first()
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    /// Capabilities to request, all of them by default
+    pub fn with_capabilities<I>(mut self, capabilities: I) -> Self
+    where
+        I: IntoIterator<Item = Capability>,
+    {
+        self.capabilities.clear();
+        for capability in capabilities {
+            if !self.capabilities.contains(&capability) {
+                self.capabilities.push(capability);
+            }
+        }
+        self
+    }
+
+    /// Send a PING after `interval` without receiving anything
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = Some(interval);
+        self
+    }
+
+    /// Time to wait the PONG before considering the connection dead
+    pub fn with_pong_timeout(mut self, timeout: Duration) -> Self {
+        self.pong_timeout = timeout;
+        self
+    }
+
+    /// Connection attempts and backoff, also used to reconnect
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// Chat limits of the account, `RateLimitProfile::Normal` by default
+    pub fn with_rate_limit(mut self, profile: RateLimitProfile) -> Self {
+        self.rate_limit = profile;
+        self
+    }
+
+    /// Chat messages waiting to be sent before `when_full` applies,
+    /// 1000 and `FullQueue::Block` by default, a capacity of 0 is used as 1
+    pub fn with_queue(mut self, capacity: usize, when_full: FullQueue) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self.full_queue = when_full;
+        self
+    }
+
+    /// Server host, `irc.chat.twitch.tv` by default
+    pub fn with_host(mut self, host: String) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Server port, by default 6667 or 6697 with TLS
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Maximum time of each connection attempt
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Time to wait the server welcome after sending the credentials, 10 seconds by default
+    pub fn with_login_timeout(mut self, timeout: Duration) -> Self {
+        self.login_timeout = timeout;
+        self
+    }
+
+    /// Return `IrcError::Timeout` when nothing is received in `timeout`
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Return `IrcError::Timeout` when a write blocks for more than `timeout`
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Request the capabilities and authenticate, channels are joined
+    /// after it following the join rate limit
+    pub(super) fn login_commands(&self) -> Vec<String> {
+        let mut commands = capabilities::request(&self.capabilities);
+        if !self.anonymous {
+            commands.push(Command::Pass.build(self.oauth.clone(), self));
+        }
+        commands.push(Command::Nick.build(self.nickname.clone(), self));
+        commands
+    }
+
+    /// Check the values that can be set without the builder, like in `new`,
+    /// before they are sent to the server
+    pub(super) fn validate(&self) -> Result<(), ConfigError> {
+        if !self.anonymous {
+            if !is_valid_oauth(&self.oauth) {
+                return Err(ConfigError::InvalidOAuth);
+            }
+            if !is_valid_name(&self.nickname) {
+                return Err(ConfigError::InvalidNickname(self.nickname.clone()));
+            }
+        }
+        match self.channels.iter().find(|channel| !is_valid_name(channel)) {
+            Some(channel) => Err(ConfigError::InvalidChannel(channel.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Open a TCP stream with the configured address and timeouts
+    pub(super) fn open_tcp(&self, default_port: u16) -> IOResult<TcpStream> {
+        let address = (self.host.as_str(), self.port.unwrap_or(default_port));
+        let connection = match self.connect_timeout {
+            Some(timeout) => {
+                let mut last_err = ErrorKind::NotFound.into();
+                let mut connection = None;
+                for address in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&address, timeout) {
+                        Ok(stream) => {
+                            connection = Some(stream);
+                            break;
+                        }
+                        Err(err) => last_err = err,
+                    }
+                }
+                connection.ok_or(last_err)?
+            }
+            None => TcpStream::connect(address)?,
+        };
+        connection.set_read_timeout(Some(POLL_INTERVAL))?;
+        connection.set_write_timeout(self.write_timeout)?;
+        Ok(connection)
+    }
+
+    /// Trust only these root certificates in TLS connections instead of the
+    /// webpki roots, useful to test against a local server
+    #[cfg(feature = "tls")]
+    pub fn with_root_certificates(mut self, certificates: Vec<CertificateDer<'static>>) -> Self {
+        self.root_certificates = certificates;
+        self
+    }
+}
+
+impl Default for LocoConfigBuilder {
+    fn default() -> Self {
+        Self {
+            oauth: None,
+            nickname: None,
+            channels: Vec::new(),
+            anonymous: false,
+            config: LocoConfig::empty(),
+        }
+    }
+}
+
+impl LocoConfigBuilder {
+    /// OAuth token, with or without `oauth:`
+    pub fn oauth(mut self, oauth: impl Into<String>) -> Self {
+        self.oauth = Some(oauth.into());
+        self
+    }
+
+    pub fn nickname(mut self, nickname: impl Into<String>) -> Self {
+        self.nickname = Some(nickname.into());
+        self
+    }
+
+    /// Channel to join when connecting, with or without `#`
+    pub fn channel(mut self, channel: impl Into<String>) -> Self {
+        self.channels.push(channel.into());
+        self
+    }
+
+    /// Channels to join when connecting, with or without `#`
+    pub fn channels<I, S>(mut self, channels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.channels.extend(channels.into_iter().map(Into::into));
+        self
+    }
+
+    /// Login as a random `justinfan` user, no oauth or nickname needed,
+    /// the connection can only read the chat
+    pub fn anonymous(mut self) -> Self {
+        self.anonymous = true;
+        self
+    }
+
+    /// See `LocoConfig::with_capabilities`
+    pub fn capabilities<I>(mut self, capabilities: I) -> Self
+    where
+        I: IntoIterator<Item = Capability>,
+    {
+        self.config = self.config.with_capabilities(capabilities);
+        self
+    }
+
+    /// See `LocoConfig::with_ping_interval`
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.config = self.config.with_ping_interval(interval);
+        self
+    }
+
+    /// See `LocoConfig::with_pong_timeout`
+    pub fn pong_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_pong_timeout(timeout);
+        self
+    }
+
+    /// See `LocoConfig::with_reconnect`
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.config = self.config.with_reconnect(policy);
+        self
+    }
+
+    /// See `LocoConfig::with_rate_limit`
+    pub fn rate_limit(mut self, profile: RateLimitProfile) -> Self {
+        self.config = self.config.with_rate_limit(profile);
+        self
+    }
+
+    /// See `LocoConfig::with_queue`, `build` fails with a capacity of 0
+    pub fn queue(mut self, capacity: usize, when_full: FullQueue) -> Self {
+        self.config = self.config.with_queue(capacity, when_full);
+        self.config.queue_capacity = capacity;
+        self
+    }
+
+    /// See `LocoConfig::with_host`
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.config = self.config.with_host(host.into());
+        self
+    }
+
+    /// See `LocoConfig::with_port`
+    pub fn port(mut self, port: u16) -> Self {
+        self.config = self.config.with_port(port);
+        self
+    }
+
+    /// See `LocoConfig::with_connect_timeout`
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_connect_timeout(timeout);
+        self
+    }
+
+    /// See `LocoConfig::with_login_timeout`
+    pub fn login_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_login_timeout(timeout);
+        self
+    }
+
+    /// See `LocoConfig::with_read_timeout`
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_read_timeout(timeout);
+        self
+    }
+
+    /// See `LocoConfig::with_write_timeout`
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_write_timeout(timeout);
+        self
+    }
+
+    /// See `LocoConfig::with_root_certificates`
+    #[cfg(feature = "tls")]
+    pub fn root_certificates(mut self, certificates: Vec<CertificateDer<'static>>) -> Self {
+        self.config = self.config.with_root_certificates(certificates);
+        self
+    }
+
+    /// Normalize and validate the values
+    pub fn build(self) -> Result<LocoConfig, ConfigError> {
+        let mut config = self.config;
+        if self.anonymous {
+            config.nickname = anonymous_nickname();
+            config.anonymous = true;
+        } else {
+            let oauth = self.oauth.ok_or(ConfigError::MissingOAuth)?;
+            config.oauth = oauth_token(&oauth);
+            if !is_valid_oauth(&config.oauth) {
+                return Err(ConfigError::InvalidOAuth);
+            }
+            let nickname = self.nickname.ok_or(ConfigError::MissingNickname)?;
+            config.nickname = nickname.trim().to_lowercase();
+            if !is_valid_name(&config.nickname) {
+                return Err(ConfigError::InvalidNickname(nickname));
+            }
+        }
+        if config.queue_capacity == 0 {
+            return Err(ConfigError::InvalidQueueCapacity);
+        }
+        for channel in self.channels {
+            if !is_valid_name(&channel_name(&channel)) {
+                return Err(ConfigError::InvalidChannel(channel));
+            }
+            config = config.with_channels([channel]);
+        }
+        Ok(config)
+    }
+}
+
+/// Token without `oauth:` prefix, it is added by `Command::Pass`
+fn oauth_token(oauth: &str) -> String {
+    let oauth = oauth.trim();
+    oauth.strip_prefix("oauth:").unwrap_or(oauth).into()
+}
+
+fn anonymous_nickname() -> String {
+    format!("justinfan{}", 10_000 + random() % 90_000)
+}
+
+fn is_valid_oauth(oauth: &str) -> bool {
+    !oauth.is_empty() && oauth.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Channel name without `#` and in lowercase
+pub(super) fn channel_name(channel: &str) -> String {
+    channel.trim_start_matches('#').to_lowercase()
+}
+
+/// Channel name like `channel_name`, `IrcError::InvalidChannel` when it is not a valid name
+pub(super) fn checked_channel(channel: &str) -> Result<String, IrcError> {
+    let name = channel_name(channel);
+    if is_valid_name(&name) {
+        Ok(name)
+    } else {
+        Err(IrcError::InvalidChannel(channel.into()))
+    }
+}
+
+/// Text sent in a command can not have CR or LF, the rest would be sent as other commands
+pub(super) fn check_text(text: &str) -> Result<(), IrcError> {
+    if text.contains(['\r', '\n']) {
+        return Err(IrcError::LineBreak);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_values() {
+        let config = LocoConfig::builder()
+            .oauth(" oauth:abc123 ")
+            .nickname("Foo_Bar")
+            .channel("#Baz")
+            .channels(["baz", "qux"])
+            .build()
+            .unwrap();
+        assert_eq!("abc123", config.oauth);
+        assert_eq!("foo_bar", config.nickname);
+        assert_eq!(vec!["baz", "qux"], config.channels);
+
+        let config = LocoConfig::new("oauth:abc".into(), "Foo".into(), "#Bar".into());
+        assert_eq!("abc", config.oauth);
+        assert_eq!("foo", config.nickname);
+        assert_eq!(vec!["bar"], config.channels);
+    }
+
+    #[test]
+    fn validate_values() {
+        let builder = LocoConfig::builder().oauth("abc").nickname("foo");
+        let inputs = [
+            (
+                LocoConfig::builder().nickname("foo"),
+                ConfigError::MissingOAuth,
+            ),
+            (
+                LocoConfig::builder().oauth("oauth:").nickname("foo"),
+                ConfigError::InvalidOAuth,
+            ),
+            (
+                LocoConfig::builder().oauth("abc"),
+                ConfigError::MissingNickname,
+            ),
+            (
+                LocoConfig::builder().oauth("abc").nickname("foo bar"),
+                ConfigError::InvalidNickname("foo bar".into()),
+            ),
+            (
+                builder.clone().channel("##"),
+                ConfigError::InvalidChannel("##".into()),
+            ),
+            (
+                builder.clone().channel("a".repeat(26)),
+                ConfigError::InvalidChannel("a".repeat(26)),
+            ),
+            (
+                builder.queue(0, FullQueue::Block),
+                ConfigError::InvalidQueueCapacity,
+            ),
+        ];
+        for (builder, expected) in inputs {
+            assert_eq!(Some(expected), builder.build().err());
+        }
+
+        let config = LocoConfig::new("abc".into(), "foo".into(), "bar\r\nQUIT".into());
+        assert_eq!(
+            Err(ConfigError::InvalidChannel("bar\r\nquit".into())),
+            config.validate()
+        );
+        let config = LocoConfig::new("abc".into(), "foo\r\nQUIT".into(), "bar".into());
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidNickname(_))
+        ));
+        let config = LocoConfig::new("abc\r\nQUIT".into(), "foo".into(), "bar".into());
+        assert_eq!(Err(ConfigError::InvalidOAuth), config.validate());
+        assert_eq!(Ok(()), LocoConfig::anonymous("bar".into()).validate());
+    }
+
+    #[test]
+    fn anonymous_login() {
+        let config = LocoConfig::builder()
+            .anonymous()
+            .channel("foo")
+            .build()
+            .unwrap();
+        assert!(config.is_anonymous());
+        assert!(config.nickname.starts_with("justinfan"));
+        assert!(is_valid_name(&config.nickname));
+        assert!(!LocoConfig::new("abc".into(), "foo".into(), "bar".into()).is_anonymous());
+    }
+}
@@ -0,0 +1,404 @@
+use std::time::{Duration, SystemTime};
+
+use super::{
+    Badge, Color, Emote, GlobalUserState, Irc, IrcType, NoticeKind, RoomState, UserNoticeKind,
+    UserState, DEFAULT_NONE,
+};
+
+/// Typed IRC event, converted from an `Irc` with `TwitchEvent::from`
+#[derive(Debug)]
+pub enum TwitchEvent {
+    Privmsg(PrivmsgEvent),
+    ClearChat(ClearChatEvent),
+    ClearMsg(ClearMsgEvent),
+    UserNotice(UserNoticeEvent),
+    RoomState(RoomStateEvent),
+    UserState(UserStateEvent),
+    GlobalUserState(GlobalUserStateEvent),
+    Notice(NoticeEvent),
+    HostTarget(HostTargetEvent),
+    Whisper(WhisperEvent),
+    /// Twitch is going to restart the server, the connection will reconnect
+    Reconnect,
+    Join(JoinEvent),
+    Part(PartEvent),
+    /// Events without payload, like PING or the numeric replies
+    Other(Irc),
+}
+
+/// Author of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sender {
+    /// Login name, in lowercase
+    pub login: String,
+    pub display_name: Option<String>,
+    pub user_id: Option<String>,
+    pub badges: Vec<Badge>,
+    pub color: Option<Color>,
+}
+
+/// Chat message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivmsgEvent {
+    pub channel: String,
+    pub room_id: Option<String>,
+    pub sender: Sender,
+    pub message_id: Option<String>,
+    pub text: String,
+    pub emotes: Vec<Emote>,
+    pub bits: Option<u64>,
+    pub is_mod: bool,
+    pub is_subscriber: bool,
+    pub sent_at: Option<SystemTime>,
+}
+
+/// Chat cleared or messages of a user removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClearChatEvent {
+    pub channel: String,
+    pub room_id: Option<String>,
+    pub action: ClearChatAction,
+    pub sent_at: Option<SystemTime>,
+}
+
+/// What a CLEARCHAT removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClearChatAction {
+    /// All the messages of the chat
+    ClearAll,
+    /// Messages of a user permanently banned
+    Ban {
+        login: String,
+        user_id: Option<String>,
+    },
+    /// Messages of a user that can not chat during `duration`
+    Timeout {
+        login: String,
+        user_id: Option<String>,
+        duration: Duration,
+    },
+}
+
+/// Single message deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClearMsgEvent {
+    pub channel: String,
+    pub room_id: Option<String>,
+    /// Login of the author of the message
+    pub login: String,
+    pub target_message_id: String,
+    /// Text of the deleted message
+    pub text: String,
+    pub sent_at: Option<SystemTime>,
+}
+
+/// Subscriptions, raids, announcements and other chat notifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserNoticeEvent {
+    pub channel: String,
+    pub room_id: Option<String>,
+    pub sender: Sender,
+    /// Kind of notification, like `sub` or `raid`
+    pub msg_id: String,
+    pub kind: UserNoticeKind,
+    /// Text displayed by Twitch describing the notification
+    pub system_message: Option<String>,
+    /// Message written by the user, if any
+    pub text: Option<String>,
+    pub sent_at: Option<SystemTime>,
+}
+
+/// Chat settings of a channel, all of them after joining or the ones that changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomStateEvent {
+    pub channel: String,
+    pub state: RoomState,
+}
+
+/// State of the bot in a channel, sent after joining or sending a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserStateEvent {
+    pub channel: String,
+    pub state: UserState,
+}
+
+/// State of the bot after logging in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalUserStateEvent {
+    pub state: GlobalUserState,
+}
+
+/// Message from the server, like a rejected chat message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoticeEvent {
+    /// `None` for notices not related to a channel, like a failed login
+    pub channel: Option<String>,
+    pub kind: NoticeKind,
+    pub text: String,
+}
+
+/// Channel started or stopped hosting another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostTargetEvent {
+    pub channel: String,
+    /// Hosted channel, `None` when hosting ended
+    pub target: Option<String>,
+    pub viewers: Option<u32>,
+}
+
+/// Private message to the bot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhisperEvent {
+    pub sender: Sender,
+    pub message_id: Option<String>,
+    pub text: String,
+}
+
+/// User joined a channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinEvent {
+    pub channel: String,
+    pub login: String,
+}
+
+/// User left a channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartEvent {
+    pub channel: String,
+    pub login: String,
+}
+
+impl From<Irc> for TwitchEvent {
+    fn from(irc: Irc) -> Self {
+        match irc.irc_type {
+            IrcType::Message => Self::Privmsg(PrivmsgEvent {
+                channel: irc.channel.clone(),
+                room_id: owned(irc.room_id()),
+                sender: Sender::from_irc(&irc),
+                message_id: owned(irc.message_id()),
+                text: irc.message.clone().unwrap_or_default(),
+                emotes: irc.emotes(),
+                bits: irc.bits(),
+                is_mod: irc.is_mod(),
+                is_subscriber: irc.is_subscriber(),
+                sent_at: irc.tmi_sent_ts(),
+            }),
+            IrcType::CleanChat => Self::ClearChat(ClearChatEvent {
+                channel: irc.channel.clone(),
+                room_id: owned(irc.room_id()),
+                action: ClearChatAction::from_irc(&irc),
+                sent_at: irc.tmi_sent_ts(),
+            }),
+            IrcType::ClearMsg => Self::ClearMsg(ClearMsgEvent {
+                channel: irc.channel.clone(),
+                room_id: owned(irc.room_id()).filter(|room_id| !room_id.is_empty()),
+                login: owned(irc.tag("login")).unwrap_or_default(),
+                target_message_id: owned(irc.tag("target-msg-id")).unwrap_or_default(),
+                text: irc.message.clone().unwrap_or_default(),
+                sent_at: irc.tmi_sent_ts(),
+            }),
+            IrcType::Usernotice => Self::UserNotice(UserNoticeEvent {
+                channel: irc.channel.clone(),
+                room_id: owned(irc.room_id()),
+                sender: Sender::from_irc(&irc),
+                msg_id: owned(irc.tag("msg-id")).unwrap_or_default(),
+                kind: UserNoticeKind::from_irc(&irc),
+                system_message: owned(irc.tag("system-msg")),
+                text: irc.message.clone(),
+                sent_at: irc.tmi_sent_ts(),
+            }),
+            IrcType::RoomState => Self::RoomState(RoomStateEvent {
+                channel: irc.channel.clone(),
+                state: RoomState::from_irc(&irc),
+            }),
+            IrcType::UserState => Self::UserState(UserStateEvent {
+                channel: irc.channel.clone(),
+                state: UserState::from_irc(&irc),
+            }),
+            IrcType::GlobalUserState => Self::GlobalUserState(GlobalUserStateEvent {
+                state: GlobalUserState::from_irc(&irc),
+            }),
+            IrcType::Notice => Self::Notice(NoticeEvent {
+                channel: (irc.channel != DEFAULT_NONE).then(|| irc.channel.clone()),
+                kind: NoticeKind::from_irc(&irc),
+                text: irc.message.unwrap_or_default(),
+            }),
+            IrcType::HostTarget => {
+                let text = irc.message.unwrap_or_default();
+                let mut parts = text.split(' ');
+                Self::HostTarget(HostTargetEvent {
+                    channel: irc.channel,
+                    target: parts
+                        .next()
+                        .filter(|target| !target.is_empty() && *target != "-")
+                        .map(str::to_owned),
+                    viewers: parts.next().and_then(|viewers| viewers.parse().ok()),
+                })
+            }
+            IrcType::Whisper => Self::Whisper(WhisperEvent {
+                sender: Sender::from_irc(&irc),
+                message_id: owned(irc.tag("message-id")),
+                text: irc.message.unwrap_or_default(),
+            }),
+            IrcType::Reconnect => Self::Reconnect,
+            IrcType::Join => Self::Join(JoinEvent {
+                channel: irc.channel,
+                login: irc.nickname.unwrap_or_default(),
+            }),
+            IrcType::Part => Self::Part(PartEvent {
+                channel: irc.channel,
+                login: irc.nickname.unwrap_or_default(),
+            }),
+            _ => Self::Other(irc),
+        }
+    }
+}
+
+impl ClearChatAction {
+    fn from_irc(irc: &Irc) -> Self {
+        let Some(login) = irc.nickname.clone() else {
+            return Self::ClearAll;
+        };
+        let user_id = owned(irc.tag("target-user-id"));
+        let duration = irc
+            .tag("ban-duration")
+            .and_then(|seconds| seconds.parse().ok())
+            .map(Duration::from_secs);
+        match duration {
+            Some(duration) => Self::Timeout {
+                login,
+                user_id,
+                duration,
+            },
+            None => Self::Ban { login, user_id },
+        }
+    }
+}
+
+impl Sender {
+    fn from_irc(irc: &Irc) -> Self {
+        let login = match irc.irc_type {
+            IrcType::Message | IrcType::Whisper => irc.nickname.as_deref(),
+            _ => irc.tag("login"),
+        };
+        Self {
+            login: owned(login).unwrap_or_default(),
+            display_name: owned(irc.display_name()),
+            user_id: owned(irc.user_id()),
+            badges: irc.badges(),
+            color: irc.color(),
+        }
+    }
+}
+
+fn owned(value: Option<&str>) -> Option<String> {
+    value.map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::parser::Parser;
+
+    fn event(line: &str) -> TwitchEvent {
+        TwitchEvent::from(Parser.parse(line.into()).unwrap())
+    }
+
+    #[test]
+    fn convert_events() {
+        let TwitchEvent::Privmsg(privmsg) =
+            event("@display-name=Foo;user-id=1;mod=1 :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :hi")
+        else {
+            panic!("expected privmsg");
+        };
+        assert_eq!("bar", privmsg.channel);
+        assert_eq!("foo", privmsg.sender.login);
+        assert_eq!(Some("Foo".into()), privmsg.sender.display_name);
+        assert_eq!("hi", privmsg.text);
+        assert!(privmsg.is_mod);
+
+        let TwitchEvent::ClearChat(clear) = event(
+            "@ban-duration=600;room-id=2;target-user-id=3 :tmi.twitch.tv CLEARCHAT #bar :baz",
+        ) else {
+            panic!("expected clearchat");
+        };
+        assert_eq!(
+            ClearChatAction::Timeout {
+                login: "baz".into(),
+                user_id: Some("3".into()),
+                duration: Duration::from_secs(600),
+            },
+            clear.action
+        );
+        assert!(matches!(
+            event("@room-id=2;target-user-id=3 :tmi.twitch.tv CLEARCHAT #bar :baz"),
+            TwitchEvent::ClearChat(ClearChatEvent {
+                action: ClearChatAction::Ban { login, .. },
+                ..
+            }) if login == "baz"
+        ));
+        assert!(matches!(
+            event("@room-id=2 :tmi.twitch.tv CLEARCHAT #bar"),
+            TwitchEvent::ClearChat(ClearChatEvent {
+                action: ClearChatAction::ClearAll,
+                ..
+            })
+        ));
+
+        let TwitchEvent::ClearMsg(clear) = event(
+            "@login=baz;room-id=;target-msg-id=abc :tmi.twitch.tv CLEARMSG #bar :deleted text",
+        ) else {
+            panic!("expected clearmsg");
+        };
+        assert_eq!(None, clear.room_id);
+        assert_eq!(
+            ("baz", "abc", "deleted text"),
+            (
+                clear.login.as_str(),
+                clear.target_message_id.as_str(),
+                clear.text.as_str()
+            )
+        );
+
+        let TwitchEvent::Notice(notice) =
+            event(":tmi.twitch.tv NOTICE * :Login authentication failed")
+        else {
+            panic!("expected notice");
+        };
+        assert_eq!(None, notice.channel);
+        assert_eq!(NoticeKind::Authentication, notice.kind);
+        assert_eq!("Login authentication failed", notice.text);
+
+        let TwitchEvent::HostTarget(host) = event(":tmi.twitch.tv HOSTTARGET #bar :baz 10") else {
+            panic!("expected hosttarget");
+        };
+        assert_eq!((Some("baz".into()), Some(10)), (host.target, host.viewers));
+
+        let TwitchEvent::Whisper(whisper) =
+            event("@message-id=1 :foo!foo@foo.tmi.twitch.tv WHISPER bar :psst")
+        else {
+            panic!("expected whisper");
+        };
+        assert_eq!(
+            ("foo", "psst"),
+            (whisper.sender.login.as_str(), whisper.text.as_str())
+        );
+
+        assert!(matches!(
+            event(":foo!foo@foo.tmi.twitch.tv JOIN #bar"),
+            TwitchEvent::Join(JoinEvent { channel, login }) if channel == "bar" && login == "foo"
+        ));
+        assert!(matches!(
+            event("@room-id=2;slow=10 :tmi.twitch.tv ROOMSTATE #bar"),
+            TwitchEvent::RoomState(RoomStateEvent { channel, state }) if channel == "bar" && state.is_slow()
+        ));
+        assert!(matches!(
+            event(":tmi.twitch.tv RECONNECT"),
+            TwitchEvent::Reconnect
+        ));
+        assert!(matches!(
+            event("PING :tmi.twitch.tv"),
+            TwitchEvent::Other(_)
+        ));
+    }
+}
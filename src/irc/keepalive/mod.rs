@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+/// What the connection should do after a read timed out
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum KeepAliveAction {
+    Idle,
+    Ping,
+    TimedOut,
+}
+
+/// Tracks the last activity in the socket to send PINGs and detect a dead connection
+pub(super) struct KeepAlive {
+    interval: Option<Duration>,
+    timeout: Duration,
+    read_timeout: Option<Duration>,
+    last_activity: Instant,
+    ping_sent: Option<Instant>,
+}
+
+impl KeepAlive {
+    pub(super) fn new(
+        interval: Option<Duration>,
+        timeout: Duration,
+        read_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            interval,
+            timeout,
+            read_timeout,
+            last_activity: Instant::now(),
+            ping_sent: None,
+        }
+    }
+
+    /// Something was received, the connection is alive
+    pub(super) fn activity(&mut self) {
+        self.last_activity = Instant::now();
+        self.ping_sent = None;
+    }
+
+    pub(super) fn poll(&mut self, now: Instant) -> KeepAliveAction {
+        let idle = now.duration_since(self.last_activity);
+        if self
+            .read_timeout
+            .is_some_and(|read_timeout| idle >= read_timeout)
+        {
+            return KeepAliveAction::TimedOut;
+        }
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return KeepAliveAction::Idle,
+        };
+        match self.ping_sent {
+            Some(sent) if now.duration_since(sent) >= self.timeout => KeepAliveAction::TimedOut,
+            Some(_) => KeepAliveAction::Idle,
+            None if idle >= interval => {
+                self.ping_sent = Some(now);
+                KeepAliveAction::Ping
+            }
+            None => KeepAliveAction::Idle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_then_timeout() {
+        let mut keepalive =
+            KeepAlive::new(Some(Duration::from_secs(60)), Duration::from_secs(10), None);
+        let start = Instant::now();
+        assert_eq!(KeepAliveAction::Idle, keepalive.poll(start));
+        let ping_at = start + Duration::from_secs(61);
+        assert_eq!(KeepAliveAction::Ping, keepalive.poll(ping_at));
+        assert_eq!(
+            KeepAliveAction::Idle,
+            keepalive.poll(ping_at + Duration::from_secs(5))
+        );
+        assert_eq!(
+            KeepAliveAction::TimedOut,
+            keepalive.poll(ping_at + Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn disabled_without_interval() {
+        let mut keepalive = KeepAlive::new(None, Duration::ZERO, None);
+        let later = Instant::now() + Duration::from_secs(3600);
+        assert_eq!(KeepAliveAction::Idle, keepalive.poll(later));
+    }
+
+    #[test]
+    fn read_timeout_without_activity() {
+        let mut keepalive = KeepAlive::new(None, Duration::ZERO, Some(Duration::from_secs(5)));
+        let start = Instant::now();
+        assert_eq!(KeepAliveAction::Idle, keepalive.poll(start));
+        assert_eq!(
+            KeepAliveAction::TimedOut,
+            keepalive.poll(start + Duration::from_secs(5))
+        );
+    }
+}
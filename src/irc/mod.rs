@@ -1,29 +1,142 @@
 use std::{
-    collections::HashMap,
-    io::{Read, Result as IOResult, Write},
+    collections::{hash_map::RandomState, BTreeSet, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    hash::{BuildHasher, Hasher},
+    io::{ErrorKind, Read, Result as IOResult, Write},
     net::TcpStream,
+    string::FromUtf8Error,
+    sync::Arc,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use fancy_regex::Regex;
+use self::{
+    config::{channel_name, check_text, checked_channel},
+    keepalive::{KeepAlive, KeepAliveAction},
+    outbox::Outbox,
+    parser::Parser,
+    ratelimit::{Limited, RateLimiter},
+    reader::LineReader,
+};
 
-use self::parser::Parser;
+pub use self::{
+    capabilities::{Capabilities, Capability, CapabilityStatus},
+    config::{ConfigError, LocoConfig, LocoConfigBuilder},
+    event::{
+        ClearChatAction, ClearChatEvent, ClearMsgEvent, GlobalUserStateEvent, HostTargetEvent,
+        JoinEvent, NoticeEvent, PartEvent, PrivmsgEvent, RoomStateEvent, Sender, TwitchEvent,
+        UserNoticeEvent, UserStateEvent, WhisperEvent,
+    },
+    notice::{ChatMode, NoticeKind},
+    outbox::FullQueue,
+    parser::{Prefix, RawMessage},
+    ratelimit::RateLimitProfile,
+    reconnect::ReconnectPolicy,
+    roomstate::{FollowersOnly, RoomState},
+    shutdown::{ReadStatus, ShutdownHandle},
+    split::LocoReader,
+    tags::{Badge, Color, Emote},
+    usernotice::{AnnouncementColor, SubGift, SubPlan, Subscription, UserNoticeKind},
+    userstate::{GlobalUserState, UserState},
+    welcome::Welcome,
+    writer::LocoWriter,
+};
 
+#[cfg(feature = "async")]
+mod asynchronous;
+mod capabilities;
+mod config;
+mod event;
+mod keepalive;
+mod notice;
+mod outbox;
 mod parser;
+mod ratelimit;
+mod reader;
+mod reconnect;
+mod roomstate;
+mod shutdown;
+mod split;
+mod tags;
+#[cfg(feature = "tls")]
+mod tls;
+mod usernotice;
+mod userstate;
+mod welcome;
+mod writer;
+
+#[cfg(feature = "async")]
+pub use self::asynchronous::AsyncLocoConnection;
+#[cfg(feature = "tls")]
+pub use self::tls::TlsStream;
+#[cfg(feature = "tls")]
+pub use rustls::pki_types::CertificateDer;
 
 const IRC_PORT: u16 = 6667;
 const IRC_URL: &str = "irc.chat.twitch.tv";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const DEFAULT_PONG_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_QUEUE_CAPACITY: usize = 1000;
+const DEFAULT_NONE: &str = "none";
+
+/// Random number to jitter delays and generate anonymous nicknames
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
 
+/// Opens a new stream to the server, used to connect and reconnect
+type Connector<T> = Box<dyn Fn(&LocoConfig) -> IOResult<T> + Send>;
 
 #[derive(Debug)]
 /// Error types
 pub enum IrcError {
     Timeout,
-    Host(String),
-    MaxAttemps,
-    Permission,
+    /// All the connection attempts of the `ReconnectPolicy` failed, with the last error
+    MaxAttemps(Box<IrcError>),
+    /// The connection was aborted, an io `ConnectionAborted` error
     Aborted,
+    /// The connection was closed, no more commands are accepted
+    Closed,
+    /// Anonymous connections can only read the chat
+    Anonymous,
+    /// The outgoing queue is full and `FullQueue::Error` was configured
+    QueueFull,
+    /// Channel name with characters other than letters, numbers and `_`
+    InvalidChannel(String),
+    /// Text with CR or LF, it would be sent as other IRC commands
+    LineBreak,
+    /// Invalid value in a `LocoConfig` created without the builder
+    Config(ConfigError),
+    /// A line that is not a valid IRC message
+    Parse {
+        line: String,
+        reason: String,
+    },
+    /// The server closed the connection
+    Eof,
+    /// A line that is not valid UTF-8, the bytes are kept in the error
+    InvalidUtf8(FromUtf8Error),
+    Io(std::io::Error),
+    /// Wrong oauth token or nickname, "Login authentication failed" NOTICE
+    Authentication,
+    /// The oauth token is not valid, "Improperly formatted auth" NOTICE
+    ImproperOAuth,
+    /// The account is banned in the channel, the message was not sent
+    Banned {
+        channel: String,
+    },
+    /// The message was not sent because the account is sending messages too quickly
+    RateLimited {
+        channel: String,
+    },
+    /// Another `msg_*` NOTICE rejecting a message, like slow mode or followers only
+    Rejected {
+        channel: String,
+        msg_id: String,
+        message: String,
+    },
     Unknown,
 }
 
@@ -32,70 +145,123 @@ pub type IrcResult = Result<Irc, IrcError>;
 
 impl From<std::io::Error> for IrcError {
     fn from(err: std::io::Error) -> Self {
-        use std::io::ErrorKind;
         match err.kind() {
-            ErrorKind::ConnectionReset => Self::Host("connection reset by peer".into()),
-            ErrorKind::ConnectionRefused => Self::Host("connection refused by host".into()),
-            ErrorKind::NotFound => Self::Host("unknown host".into()),
-            ErrorKind::PermissionDenied => Self::Permission,
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => Self::Timeout,
             ErrorKind::ConnectionAborted => Self::Aborted,
-            ErrorKind::BrokenPipe => Self::Host("broken pipe".into()),
-            _ => Self::Unknown,
+            _ => Self::Io(err),
+        }
+    }
+}
+
+impl Display for IrcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Timeout => write!(f, "connection timed out"),
+            Self::MaxAttemps(last) => write!(f, "maximum connection attempts reached: {last}"),
+            Self::Aborted => write!(f, "connection aborted"),
+            Self::Closed => write!(f, "connection closed"),
+            Self::Anonymous => write!(f, "anonymous connections can not send chat messages"),
+            Self::QueueFull => write!(f, "outgoing queue is full"),
+            Self::InvalidChannel(channel) => write!(f, "invalid channel name `{channel}`"),
+            Self::LineBreak => write!(f, "text can not have line breaks"),
+            Self::Config(err) => write!(f, "invalid config: {err}"),
+            Self::Parse { line, reason } => write!(f, "invalid line `{line}`: {reason}"),
+            Self::Eof => write!(f, "connection closed by the server"),
+            Self::InvalidUtf8(_) => write!(f, "line is not valid UTF-8"),
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Authentication => write!(f, "login authentication failed"),
+            Self::ImproperOAuth => write!(f, "improperly formatted oauth token"),
+            Self::Banned { channel } => write!(f, "banned from channel `{channel}`"),
+            Self::RateLimited { channel } => write!(f, "rate limited in channel `{channel}`"),
+            Self::Rejected {
+                channel,
+                msg_id,
+                message,
+            } => write!(
+                f,
+                "message rejected in channel `{channel}`, {msg_id}: {message}"
+            ),
+            Self::Unknown => write!(f, "unknown error"),
+        }
+    }
+}
+
+impl Error for IrcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::InvalidUtf8(err) => Some(err),
+            Self::MaxAttemps(last) => Some(last.as_ref()),
+            Self::Config(err) => Some(err),
+            _ => None,
         }
     }
 }
 
 /// IRC Commands
+#[derive(Clone, Copy)]
 pub enum Command {
     /// Account OAuth Pass
-    Pass, 
+    Pass,
     /// Account nickname
-    Nick, 
+    Nick,
     /// Join a Channel
-    Join, 
+    Join,
+    /// Leave a Channel
+    Part,
     /// Pong a ping
-    Pong, 
+    Pong,
     /// Ping IRC Twitch Chat
-    Ping, 
+    Ping,
     /// Send chat message
-    Privmsg 
+    Privmsg,
+    /// Close the connection
+    Quit,
 }
 
 impl Command {
-    pub fn build<T>(&self, arg: String, connection: &LocoConnection<T>) -> String
-    where
-        T: Read + Write + Unpin,
-    {
+    pub fn build(&self, arg: String, config: &LocoConfig) -> String {
         let prefix = match self {
             Self::Pass => "PASS oauth:".into(),
             Self::Nick => "NICK ".into(),
             Self::Join => "JOIN #".into(),
+            Self::Part => "PART #".into(),
             Self::Pong => "PONG :tmi.twitch.tv".into(),
-            Self::Ping => "PING".into(),
-            Self::Privmsg => format!("PRIVMSG #{} :", connection.config.channel_to_join.clone()),
+            Self::Ping => "PING :tmi.twitch.tv".into(),
+            Self::Privmsg => format!("PRIVMSG #{} :", config.default_channel()),
+            Self::Quit => "QUIT".into(),
         };
         format!("{}{}\r\n", prefix, &arg)
     }
 }
 
-
-/// Connection T is only for a mock in tests, 
+/// Connection T is only for a mock in tests,
 /// Use new method instead
 pub struct LocoConnection<T>
 where
     T: Read + Write + Unpin,
 {
     connection: Option<T>,
-    config: LocoConfig,
-}
-
-
-/// Configuration of authentication in IRC Twitch Chat
-#[derive(Clone)]
-pub struct LocoConfig {
-    oauth: String,
-    nickname: String,
-    channel_to_join: String,
+    config: Arc<LocoConfig>,
+    outbox: Outbox,
+    limiter: RateLimiter,
+    reader: LineReader,
+    keepalive: KeepAlive,
+    connector: Option<Connector<T>>,
+    reconnect_pending: bool,
+    /// Channels requested with join or confirmed, joined again after reconnecting
+    wanted_channels: BTreeSet<String>,
+    /// Channels confirmed by the server
+    joined_channels: BTreeSet<String>,
+    room_states: HashMap<String, RoomState>,
+    /// Identity of the bot and its state in each joined channel
+    global_user_state: Option<GlobalUserState>,
+    user_states: HashMap<String, UserState>,
+    shutdown: ShutdownHandle,
+    welcome: Option<Welcome>,
+    capabilities: Capabilities,
+    /// Events received while waiting the welcome, returned by the next reads
+    backlog: VecDeque<Irc>,
 }
 
 /// IRC event
@@ -105,11 +271,12 @@ pub struct Irc {
     pub irc_type: IrcType,
     /// Only have nickname in event
     pub nickname: Option<String>,
-    /// Message if as PRIVMSG event
+    /// Unescaped IRCv3 tags of the event
     pub keys: Option<HashMap<String, String>>,
     /// Channel of event
     pub channel: String,
-    /// Message if as PRIVMSG event
+    /// Text of PRIVMSG, NOTICE, USERNOTICE, CLEARMSG, HOSTTARGET and WHISPER events,
+    /// the capabilities in CAP replies
     pub message: Option<String>,
 }
 
@@ -129,6 +296,26 @@ impl Irc {
             message,
         }
     }
+
+    /// The error reported by a NOTICE, like a failed login or a rejected message
+    pub fn error(&self) -> Option<IrcError> {
+        if !matches!(self.irc_type, IrcType::Notice) {
+            return None;
+        }
+        let channel = self.channel.clone();
+        match NoticeKind::from_irc(self) {
+            NoticeKind::Authentication => Some(IrcError::Authentication),
+            NoticeKind::ImproperOAuth => Some(IrcError::ImproperOAuth),
+            NoticeKind::Banned => Some(IrcError::Banned { channel }),
+            NoticeKind::RateLimited => Some(IrcError::RateLimited { channel }),
+            kind if kind.is_rejection() => Some(IrcError::Rejected {
+                channel,
+                msg_id: self.tag("msg-id").unwrap_or_default().into(),
+                message: self.message.clone().unwrap_or_default(),
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -137,97 +324,223 @@ pub enum IrcType {
     Join,
     Part,
     Usernotice,
+    /// Chat cleared, or messages of a banned user removed
     CleanChat,
     Pong,
     Ping,
     UserState,
+    GlobalUserState,
+    RoomState,
+    /// A single message was deleted
+    ClearMsg,
+    HostTarget,
+    Whisper,
     Notice,
+    /// Twitch is going to restart the server, the connection will reconnect
+    Reconnect,
+    /// Not sent by the server, the connection was lost and a new one was opened
+    Reconnected,
+    /// Capabilities enabled by the server
+    CapAck,
+    /// Capabilities refused by the server
+    CapNak,
     Unknown,
 }
 
-impl IrcType {
-    #[doc(hidden)]
-    fn display_name(&self) -> Regex {
-        let expr = match self {
-            Self::Message => r"(?<=:)(\w+)(?=!)",
-            Self::Join => r"(?<=:)(\w+)(?=!)",
-            Self::Part => r"(?<=:)(\w+)(?=!)",
-            Self::Usernotice => r"(?<=display-name=)([\w]+)",
-            Self::CleanChat => r"(?<=:)([\w]+)(?!.)",
-            Self::Pong => r"TODOU",
-            Self::Ping => r"TODOU",
-            Self::UserState => r"(?<=display-name=)([\w]+)",
-            Self::Notice => r"TODOU",
-            _ => r"TODOU",
-        };
-        Regex::new(expr).unwrap()
-    }
-}
-
 #[doc(hidden)]
-impl From<String> for IrcType {
-    fn from(value: String) -> Self {
-        match &value[..] {
+impl From<&str> for IrcType {
+    fn from(value: &str) -> Self {
+        match value {
             "PRIVMSG" => Self::Message,
             "JOIN" => Self::Join,
             "PART" => Self::Part,
             "USERNOTICE" => Self::Usernotice,
             "CLEARCHAT" => Self::CleanChat,
+            "CLEARMSG" => Self::ClearMsg,
+            "USERSTATE" => Self::UserState,
+            "GLOBALUSERSTATE" => Self::GlobalUserState,
+            "ROOMSTATE" => Self::RoomState,
+            "HOSTTARGET" => Self::HostTarget,
+            "WHISPER" => Self::Whisper,
             "PING" => Self::Ping,
             "PONG" => Self::Pong,
             "NOTICE" => Self::Notice,
+            "RECONNECT" => Self::Reconnect,
             _ => Self::Unknown,
         }
     }
 }
 
-impl LocoConfig {
-    /// Returns a Config Object
-    pub fn new(oauth: String, nickname: String, channel_to_join: String) -> Self {
-        Self {
-            oauth,
-            nickname,
-            channel_to_join,
-        }
-    }
-}
-
 impl LocoConnection<TcpStream> {
     /// Initialize a Tcp Connection
     pub fn new(loco_config: LocoConfig) -> Result<LocoConnection<TcpStream>, IrcError> {
-        let con: LocoConnection<TcpStream> = LocoConnection::try_connect(loco_config)?;
+        let mut con = LocoConnection::from_stream(None, loco_config);
+        con.connector = Some(Box::new(Self::open));
+        con.establish()?;
         Ok(con)
     }
 
-    fn try_connect(loco_config: LocoConfig) -> Result<LocoConnection<TcpStream>, IrcError> {
-        const MAX_ATTEMPS: usize = 3;
-        for attempt in 0..MAX_ATTEMPS {
-            println!("connection attempt {att}", att = attempt + 1);
-            match TcpStream::connect(&format!("{}:{}", IRC_URL, IRC_PORT)) {
+    fn open(loco_config: &LocoConfig) -> IOResult<TcpStream> {
+        loco_config.open_tcp(IRC_PORT)
+    }
+}
+
+impl<T> LocoConnection<T>
+where
+    T: Read + Write + Unpin,
+{
+    fn from_stream(connection: Option<T>, config: LocoConfig) -> Self {
+        let keepalive = KeepAlive::new(
+            config.ping_interval,
+            config.pong_timeout,
+            config.read_timeout,
+        );
+        let wanted_channels = config.channels.iter().cloned().collect();
+        Self {
+            connection,
+            limiter: RateLimiter::new(config.rate_limit),
+            outbox: Outbox::new(config.queue_capacity, config.full_queue),
+            config: Arc::new(config),
+            reader: LineReader::default(),
+            keepalive,
+            connector: None,
+            reconnect_pending: false,
+            wanted_channels,
+            joined_channels: BTreeSet::new(),
+            room_states: HashMap::new(),
+            global_user_state: None,
+            user_states: HashMap::new(),
+            shutdown: ShutdownHandle::default(),
+            welcome: None,
+            capabilities: Capabilities::default(),
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Open a new stream with the connector following the reconnect policy
+    /// and authenticate, request the capabilities and join the channels again
+    fn establish(&mut self) -> Result<(), IrcError> {
+        let policy = self.config.reconnect.clone();
+        let mut last = IrcError::Unknown;
+        for attempt in 0..policy.max_attempts() {
+            let connection = match &self.connector {
+                Some(connector) => connector(&self.config),
+                None => return Err(IrcError::Unknown),
+            };
+            let result = match connection {
                 Ok(connection) => {
-                    let mut loco_connection = LocoConnection {
-                        connection: Some(connection),
-                        config: loco_config.clone(),
-                    };
-                    loco_connection.batch_command(&[
-                        Command::Pass.build(loco_config.oauth.clone(), &loco_connection),
-                        Command::Nick.build(loco_config.nickname.clone(), &loco_connection),
-                        Command::Join.build(loco_config.channel_to_join, &loco_connection),
-                        "CAP REQ :twitch.tv/commands\r\n".into(),
-                        "CAP REQ :twitch.tv/membership\r\n".into(),
-                        "CAP REQ :twitch.tv/tags\r\n".into(),
-                    ])?;
-                    return Ok(loco_connection);
+                    self.connection = Some(connection);
+                    self.reader = LineReader::default();
+                    self.keepalive = KeepAlive::new(
+                        self.config.ping_interval,
+                        self.config.pong_timeout,
+                        self.config.read_timeout,
+                    );
+                    self.reconnect_pending = false;
+                    self.joined_channels.clear();
+                    self.room_states.clear();
+                    self.global_user_state = None;
+                    for channel in std::mem::take(&mut self.user_states).into_keys() {
+                        self.limiter.set_privileged(&channel, false);
+                    }
+                    self.backlog.clear();
+                    self.capabilities = Capabilities::requested(&self.config.capabilities);
+                    self.login()
+                }
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                // other attempts would be rejected too
+                Err(
+                    err
+                    @ (IrcError::Authentication | IrcError::ImproperOAuth | IrcError::Config(_)),
+                ) => {
+                    self.connection = None;
+                    return Err(err);
                 }
-                _ => {
-                    if attempt == MAX_ATTEMPS {
-                        return Err(IrcError::MaxAttemps);
+                Err(err) => {
+                    self.connection = None;
+                    last = err;
+                    if attempt + 1 < policy.max_attempts() {
+                        thread::sleep(policy.delay(attempt))
                     }
-                    thread::sleep(Duration::from_secs((2_u64).pow(attempt as u32)))
                 }
             }
         }
-        Err(IrcError::Unknown)
+        Err(IrcError::MaxAttemps(Box::new(last)))
+    }
+
+    fn login(&mut self) -> Result<(), IrcError> {
+        self.config.validate().map_err(IrcError::Config)?;
+        self.batch_command(&self.config.login_commands())?;
+        self.wait_welcome()?;
+        let joins = self
+            .wanted_channels
+            .iter()
+            .map(|channel| Command::Join.build(channel.clone(), &self.config))
+            .collect();
+        self.outbox.requeue(joins);
+        self.flush()?;
+        Ok(())
+    }
+
+    /// Read until the `001` welcome or GLOBALUSERSTATE, a failed login returns
+    /// `IrcError::Authentication` or `IrcError::ImproperOAuth`
+    fn wait_welcome(&mut self) -> Result<(), IrcError> {
+        let deadline = Instant::now() + self.config.login_timeout;
+        loop {
+            let connection = self.connection.as_mut().ok_or(IrcError::Closed)?;
+            let line = match self.reader.read_line(connection) {
+                Ok(Some(line)) => line,
+                Ok(None) => return Err(IrcError::Eof),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if Instant::now() >= deadline {
+                        return Err(IrcError::Timeout);
+                    }
+                    continue;
+                }
+                // the rest of a line too long is skipped like the invalid ones
+                Err(err) if err.kind() == ErrorKind::InvalidData => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let Ok(msg) = String::from_utf8(line) else {
+                continue;
+            };
+            let Some(raw) = RawMessage::parse(&msg) else {
+                continue;
+            };
+            let welcome = match raw.command {
+                "001" => Some(Welcome::from_raw(&raw)),
+                _ => None,
+            };
+            let done = welcome.is_some() || raw.command == "GLOBALUSERSTATE";
+            let irc = Parser.parse(msg)?;
+            self.handle(&irc)?;
+            self.backlog.push_back(irc);
+            if let Some(welcome) = welcome {
+                self.welcome = Some(welcome);
+            }
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Capabilities requested in the current connection, acknowledged or not by the server
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Welcome of the server to the current connection
+    pub fn welcome(&self) -> Option<&Welcome> {
+        self.welcome.as_ref()
+    }
+
+    fn can_reconnect(&self) -> bool {
+        self.connector.is_some()
+            && self.config.reconnect.auto_reconnect()
+            && !self.shutdown.is_requested()
     }
 
     fn batch_command(&mut self, vec: &[String]) -> IOResult<()> {
@@ -238,19 +551,337 @@ impl LocoConnection<TcpStream> {
         Ok(())
     }
 
-    /// Send a command to IRC
-    pub fn send_command(&mut self, command: Command, arg: &str) -> IOResult<()> {
-        let command = command.build(arg.into(), self);
-        self.batch_command(&[command])?;
+    /// Write the commands queued by the writers
+    /// Commands over the rate limit stay queued, in order, until there is capacity
+    fn flush(&mut self) -> IOResult<()> {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        let mut waiting = VecDeque::new();
+        let mut blocked = HashSet::new();
+        for command in self.outbox.take() {
+            match Limited::of(&command) {
+                Some(limited) if blocked.contains(&limited) => waiting.push_back(command),
+                Some(limited) => match self.limiter.acquire(&limited, now) {
+                    Ok(()) => ready.push(command),
+                    Err(_) => {
+                        blocked.insert(limited);
+                        waiting.push_back(command);
+                    }
+                },
+                None => ready.push(command),
+            }
+        }
+        self.outbox.requeue(waiting);
+        if ready.is_empty() {
+            return Ok(());
+        }
+        self.batch_command(&ready)
+    }
+
+    /// Handle to send commands from other threads while this connection is read
+    pub fn writer(&self) -> LocoWriter {
+        LocoWriter {
+            outbox: self.outbox.clone(),
+            config: self.config.clone(),
+            blocking: true,
+        }
+    }
+
+    /// Queue commands from the reading thread, it can not wait for space so with
+    /// `FullQueue::Block` the queue is written once and `IrcError::QueueFull` is
+    /// returned when it is still full
+    fn enqueue(
+        &mut self,
+        send: impl Fn(&LocoWriter) -> Result<(), IrcError>,
+    ) -> Result<(), IrcError> {
+        let writer = LocoWriter {
+            blocking: false,
+            ..self.writer()
+        };
+        match send(&writer) {
+            Err(IrcError::QueueFull) if self.outbox.when_full() == FullQueue::Block => {
+                self.flush()?;
+                send(&writer)?;
+            }
+            result => result?,
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    /// Number of commands waiting to be written
+    pub fn queue_depth(&self) -> usize {
+        self.outbox.depth()
+    }
+
+    /// Remove the commands waiting to be written without sending them, like the ones
+    /// over the rate limit left by `shutdown`
+    pub fn drain_queue(&mut self) -> Vec<String> {
+        Vec::from(self.outbox.take())
+    }
+
+    /// Split in a reader, that only reads the events, and a cloneable and thread-safe
+    /// writer, commands sent by the writer are written while the connection is read
+    pub fn split(self) -> (LocoReader<T>, LocoWriter) {
+        let writer = self.writer();
+        (LocoReader { connection: self }, writer)
+    }
+
+    /// Handle to stop the connection from another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Write the pending commands allowed by the rate limiter, part the joined channels,
+    /// send QUIT and close the socket, next reads return `None`.
+    /// Commands over the rate limit are not sent, they are kept for `drain_queue`
+    pub fn shutdown(&mut self) -> Result<(), IrcError> {
+        self.shutdown.shutdown();
+        if self.connection.is_none() {
+            return Ok(());
+        }
+        let flushed = self.flush();
+        let mut commands = Vec::new();
+        for channel in &self.joined_channels {
+            commands.push(Command::Part.build(channel.clone(), &self.config));
+        }
+        commands.push(Command::Quit.build(String::new(), &self.config));
+        self.outbox.close();
+        let result = flushed
+            .and_then(|_| self.batch_command(&commands))
+            .and_then(|_| match &mut self.connection {
+                Some(connection) => connection.flush(),
+                None => Ok(()),
+            });
+        self.connection = None;
+        self.joined_channels.clear();
+        result?;
         Ok(())
     }
 
-    /// Another way to handle messages, but cannot send commands with the same connection
-    //TODO: greceful shutdown
-    pub fn read(&mut self, exec: impl Fn(Irc)) {
-        for irc in self {
+    /// Another way to handle messages, use `writer` to send commands while reading
+    /// and `shutdown_handle` to stop it. The writers do not wait for space in `exec`,
+    /// a full queue returns `IrcError::QueueFull` because only reading empties it
+    pub fn read(&mut self, mut exec: impl FnMut(Irc)) -> Result<ReadStatus, IrcError> {
+        while let Some(irc) = self.next_irc()? {
             exec(irc)
         }
+        if self.shutdown.is_requested() {
+            Ok(ReadStatus::Shutdown)
+        } else {
+            Ok(ReadStatus::Closed)
+        }
+    }
+
+    /// Send a command to IRC, chat messages fail with `IrcError::Anonymous`
+    /// in anonymous connections
+    pub fn send_command(&mut self, command: Command, arg: &str) -> Result<(), IrcError> {
+        self.enqueue(|writer| writer.send_command(command, arg))
+    }
+
+    /// Join a channel, it is listed in `channels` after the server confirms
+    pub fn join(&mut self, channel: &str) -> Result<(), IrcError> {
+        self.enqueue(|writer| writer.join(channel))?;
+        self.wanted_channels.insert(channel_name(channel));
+        Ok(())
+    }
+
+    /// Leave a channel
+    pub fn part(&mut self, channel: &str) -> Result<(), IrcError> {
+        self.enqueue(|writer| writer.part(channel))?;
+        self.wanted_channels.remove(&channel_name(channel));
+        Ok(())
+    }
+
+    /// Send a chat message to a channel
+    pub fn privmsg(&mut self, channel: &str, text: &str) -> Result<(), IrcError> {
+        self.enqueue(|writer| writer.privmsg(channel, text))
+    }
+
+    /// Chat settings of a joined channel, with or without `#`, merged from all
+    /// the ROOMSTATE received
+    pub fn room_state(&self, channel: &str) -> Option<&RoomState> {
+        self.room_states.get(&channel_name(channel))
+    }
+
+    /// Identity of the bot sent by the server after logging in
+    pub fn global_user_state(&self) -> Option<&GlobalUserState> {
+        self.global_user_state.as_ref()
+    }
+
+    /// State of the bot in a joined channel, with or without `#`, like its badges
+    /// and if it is moderator
+    pub fn user_state(&self, channel: &str) -> Option<&UserState> {
+        self.user_states.get(&channel_name(channel))
+    }
+
+    /// The bot is moderator or broadcaster in the channel
+    pub fn is_moderator(&self, channel: &str) -> bool {
+        self.user_state(channel)
+            .is_some_and(UserState::is_moderator)
+    }
+
+    /// Channels currently joined, confirmed by the server, without `#`
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.joined_channels.iter().map(String::as_str)
+    }
+
+    /// Read the next IRC event, `Ok(None)` when the connection was closed.
+    /// Server PINGs are answered automatically and, with a ping interval configured,
+    /// a connection without PONG in time returns `IrcError::Timeout`.
+    /// When the connection is lost or Twitch asks to reconnect, a new connection
+    /// is opened following the `ReconnectPolicy` and `IrcType::Reconnected` is returned.
+    /// Lines that can not be parsed are skipped, see `events` to receive them as errors
+    pub fn next_irc(&mut self) -> Result<Option<Irc>, IrcError> {
+        loop {
+            match self.next_event() {
+                Err(IrcError::Parse { .. } | IrcError::InvalidUtf8(_)) => {}
+                Err(IrcError::Eof) => return Ok(None),
+                result => return result,
+            }
+        }
+    }
+
+    /// Iterate over the IRC events and the errors, like invalid lines, messages rejected
+    /// by a NOTICE or the connection closed by the server, it ends after the shutdown
+    /// or an error that stops the connection
+    pub fn events(&mut self) -> Events<'_, T> {
+        Events {
+            connection: self,
+            done: false,
+        }
+    }
+
+    /// Like `next_irc` returning the invalid lines and the end of the stream as errors
+    fn next_event(&mut self) -> Result<Option<Irc>, IrcError> {
+        if !self.reconnect_pending {
+            // only a lost or stale connection is opened again
+            match self.read_irc() {
+                Ok(None)
+                | Err(IrcError::Io(_) | IrcError::Eof | IrcError::Timeout | IrcError::Aborted)
+                    if self.can_reconnect() => {}
+                result => return result,
+            }
+        }
+        self.connection = None;
+        self.establish()?;
+        Ok(Some(Irc::new(
+            IrcType::Reconnected,
+            None,
+            None,
+            DEFAULT_NONE.into(),
+            None,
+        )))
+    }
+
+    fn read_irc(&mut self) -> Result<Option<Irc>, IrcError> {
+        self.outbox.set_reader();
+        if let Some(irc) = self.backlog.pop_front() {
+            return Ok(Some(irc));
+        }
+        loop {
+            if self.shutdown.is_requested() {
+                self.shutdown()?;
+                return Ok(None);
+            }
+            self.flush()?;
+            let connection = match self.connection.as_mut() {
+                Some(connection) => connection,
+                None => return Ok(None),
+            };
+            match self.reader.read_line(connection) {
+                Ok(Some(line)) => {
+                    self.keepalive.activity();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let msg = String::from_utf8(line).map_err(IrcError::InvalidUtf8)?;
+                    let irc = Parser.parse(msg)?;
+                    self.handle(&irc)?;
+                    return Ok(Some(irc));
+                }
+                Ok(None) => return Err(IrcError::Eof),
+                // the socket read timeout is a tick to flush the writers and keep alive
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    match self.keepalive.poll(Instant::now()) {
+                        KeepAliveAction::Ping => self.send_command(Command::Ping, "")?,
+                        KeepAliveAction::TimedOut => return Err(IrcError::Timeout),
+                        KeepAliveAction::Idle => {}
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::InvalidData => {
+                    return Err(IrcError::Parse {
+                        line: String::new(),
+                        reason: err.to_string(),
+                    })
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn handle(&mut self, irc: &Irc) -> Result<(), IrcError> {
+        match irc.irc_type {
+            IrcType::Ping => self.send_command(Command::Pong, "")?,
+            IrcType::Notice => {
+                if let Some(err @ (IrcError::Authentication | IrcError::ImproperOAuth)) =
+                    irc.error()
+                {
+                    return Err(err);
+                }
+            }
+            IrcType::Reconnect => self.reconnect_pending = true,
+            IrcType::CapAck => self.capabilities.answer(
+                irc.message.as_deref().unwrap_or_default(),
+                CapabilityStatus::Acknowledged,
+            ),
+            IrcType::CapNak => self.capabilities.answer(
+                irc.message.as_deref().unwrap_or_default(),
+                CapabilityStatus::Rejected,
+            ),
+            IrcType::Join if self.is_self(irc) => {
+                self.wanted_channels.insert(irc.channel.clone());
+                self.joined_channels.insert(irc.channel.clone());
+            }
+            IrcType::Part if self.is_self(irc) => {
+                self.wanted_channels.remove(&irc.channel);
+                self.joined_channels.remove(&irc.channel);
+                self.room_states.remove(&irc.channel);
+                self.user_states.remove(&irc.channel);
+                self.limiter.set_privileged(&irc.channel, false);
+            }
+            IrcType::UserState => {
+                let state = UserState::from_irc(irc);
+                let privileged = state.is_moderator() || state.is_vip;
+                self.limiter.set_privileged(&irc.channel, privileged);
+                self.user_states.insert(irc.channel.clone(), state);
+            }
+            IrcType::GlobalUserState => {
+                self.global_user_state = Some(GlobalUserState::from_irc(irc));
+            }
+            IrcType::RoomState => self
+                .room_states
+                .entry(irc.channel.clone())
+                .or_default()
+                .merge(RoomState::from_irc(irc)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_self(&self, irc: &Irc) -> bool {
+        irc.nickname
+            .as_ref()
+            .is_some_and(|nickname| nickname.eq_ignore_ascii_case(&self.config.nickname))
+    }
+}
+
+impl<T> Drop for LocoConnection<T>
+where
+    T: Read + Write + Unpin,
+{
+    fn drop(&mut self) {
+        self.outbox.close();
     }
 }
 
@@ -261,44 +892,725 @@ where
     type Item = Irc;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut irc: Option<Self::Item> = None;
-        if let Some(connection) = &mut self.connection {
-            let mut buf = [0; 1024];
-            if connection.read(&mut buf).is_ok() {
-                if let Ok(msg) = String::from_utf8(Vec::from(buf)) {
-                    if let Ok(value) = Parser.parse(msg) {
-                        irc = Some(value);
-                    }
-                }
+        self.next_irc().ok().flatten()
+    }
+}
+
+/// Iterator of `LocoConnection::events`
+pub struct Events<'a, T>
+where
+    T: Read + Write + Unpin,
+{
+    connection: &'a mut LocoConnection<T>,
+    done: bool,
+}
+
+impl<T> Iterator for Events<'_, T>
+where
+    T: Read + Write + Unpin,
+{
+    type Item = Result<Irc, IrcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.connection.next_event() {
+            Ok(Some(irc)) => Some(irc.error().map_or(Ok(irc), Err)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err @ (IrcError::Parse { .. } | IrcError::InvalidUtf8(_))) => Some(Err(err)),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
             }
         }
-        irc
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
+
     use super::*;
 
+    /// Stream returning each chunk in a read, an empty chunk is a read timeout
+    struct MockStream {
+        input: VecDeque<Vec<u8>>,
+        output: Arc<std::sync::Mutex<Vec<u8>>>,
+    }
+
+    impl MockStream {
+        fn new(chunks: &[&str]) -> Self {
+            Self {
+                input: chunks
+                    .iter()
+                    .map(|chunk| chunk.as_bytes().to_vec())
+                    .collect(),
+                output: Arc::default(),
+            }
+        }
+
+        fn written(conn: &LocoConnection<Self>) -> String {
+            Self::output(&conn.connection.as_ref().unwrap().output)
+        }
+
+        fn output(output: &std::sync::Mutex<Vec<u8>>) -> String {
+            String::from_utf8(output.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+            match self.input.pop_front() {
+                Some(chunk) if chunk.is_empty() => Err(ErrorKind::WouldBlock.into()),
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(&chunk);
+                    Ok(chunk.len())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+            self.output.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn build_commands() {
-        let fake_conn: LocoConnection<TcpStream> = LocoConnection {
-            connection: None,
-            config: LocoConfig {
-                oauth: "test".into(),
-                nickname: "test".into(),
-                channel_to_join: "test".into(),
-            },
-        };
+        let fake_conn: LocoConnection<TcpStream> = LocoConnection::from_stream(
+            None,
+            LocoConfig::new("test".into(), "test".into(), "test".into()),
+        );
         let inputs = [
             (Command::Join, "test", "JOIN #test\r\n"),
+            (Command::Part, "test", "PART #test\r\n"),
             (Command::Nick, "test", "NICK test\r\n"),
             (Command::Privmsg, "test", "PRIVMSG #test :test\r\n"),
             (Command::Pass, "test", "PASS oauth:test\r\n"),
+            (Command::Ping, "", "PING :tmi.twitch.tv\r\n"),
+            (Command::Pong, "", "PONG :tmi.twitch.tv\r\n"),
+            (Command::Quit, "", "QUIT\r\n"),
         ];
 
         for (command, param, expected) in inputs {
-            assert_eq!(expected, command.build(param.into(), &fake_conn))
+            assert_eq!(expected, command.build(param.into(), &fake_conn.config))
+        }
+    }
+
+    #[test]
+    fn iterate_lines() {
+        let input =
+            ":foo!foo@foo.tmi.twitch.tv JOIN #test\r\n:bar!bar@bar.tmi.twitch.tv PART #test\r\n";
+        let conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[input])),
+            LocoConfig::new("test".into(), "test".into(), "test".into()),
+        );
+        let events = conn.collect::<Vec<Irc>>();
+        assert_eq!(2, events.len());
+        assert!(matches!(events[0].irc_type, IrcType::Join));
+        assert_eq!(Some("foo".into()), events[0].nickname);
+        assert!(matches!(events[1].irc_type, IrcType::Part));
+        assert_eq!(Some("bar".into()), events[1].nickname);
+    }
+
+    #[test]
+    fn iterate_events_and_errors() {
+        let stream = MockStream {
+            input: VecDeque::from(vec![
+                b":foo!foo@foo.tmi.twitch.tv JOIN #test\r\n".to_vec(),
+                b":foo!foo@foo.tmi.twitch.tv PRIVMSG #test :\xff\r\n".to_vec(),
+                b"@badges=foo\r\n".to_vec(),
+            ]),
+            output: Arc::default(),
+        };
+        let mut conn = LocoConnection::from_stream(
+            Some(stream),
+            LocoConfig::new("test".into(), "test".into(), "test".into()),
+        );
+        let events = conn.events().collect::<Vec<_>>();
+        assert_eq!(4, events.len());
+        assert!(matches!(&events[0], Ok(irc) if matches!(irc.irc_type, IrcType::Join)));
+        assert!(matches!(&events[1], Err(IrcError::InvalidUtf8(_))));
+        assert!(matches!(
+            &events[2],
+            Err(IrcError::Parse { line, reason })
+                if line == "@badges=foo" && reason == "tags without command"
+        ));
+        assert!(matches!(events[3], Err(IrcError::Eof)));
+    }
+
+    #[test]
+    fn notice_errors() {
+        let error = |line: &str| Parser.parse(line.into()).unwrap().error();
+        assert!(matches!(
+            error(":tmi.twitch.tv NOTICE * :Login authentication failed"),
+            Some(IrcError::Authentication)
+        ));
+        assert!(matches!(
+            error(":tmi.twitch.tv NOTICE * :Improperly formatted auth"),
+            Some(IrcError::ImproperOAuth)
+        ));
+        assert!(matches!(
+            error("@msg-id=msg_banned :tmi.twitch.tv NOTICE #foo :You are permanently banned from talking in foo."),
+            Some(IrcError::Banned { channel }) if channel == "foo"
+        ));
+        let rejected =
+            error("@msg-id=msg_slowmode :tmi.twitch.tv NOTICE #foo :This room is in slow mode.")
+                .unwrap();
+        assert_eq!(
+            "message rejected in channel `foo`, msg_slowmode: This room is in slow mode.",
+            rejected.to_string()
+        );
+        assert!(error(
+            "@msg-id=slow_on :tmi.twitch.tv NOTICE #foo :This room is now in slow mode."
+        )
+        .is_none());
+
+        assert!(matches!(
+            IrcError::from(std::io::Error::from(ErrorKind::ConnectionAborted)),
+            IrcError::Aborted
+        ));
+        let io = IrcError::from(std::io::Error::other("boom"));
+        assert_eq!("boom", io.source().unwrap().to_string());
+
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[
+                ":tmi.twitch.tv NOTICE * :Login authentication failed\r\n",
+            ])),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        assert!(matches!(conn.next_irc(), Err(IrcError::Authentication)));
+    }
+
+    #[test]
+    fn answer_server_ping() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&["PING :tmi.twitch.tv\r\n"])),
+            LocoConfig::new("test".into(), "test".into(), "test".into()),
+        );
+        let irc = conn.next().unwrap();
+        assert!(matches!(irc.irc_type, IrcType::Ping));
+        assert_eq!("PONG :tmi.twitch.tv\r\n", MockStream::written(&conn));
+    }
+
+    #[test]
+    fn ping_and_timeout_without_pong() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&["", ""])),
+            LocoConfig::new("test".into(), "test".into(), "test".into())
+                .with_ping_interval(Duration::ZERO)
+                .with_pong_timeout(Duration::ZERO),
+        );
+        assert!(matches!(conn.next_irc(), Err(IrcError::Timeout)));
+        assert_eq!("PING :tmi.twitch.tv\r\n", MockStream::written(&conn));
+    }
+
+    #[test]
+    fn ping_with_full_queue() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&["", "PING :tmi.twitch.tv\r\n"])),
+            LocoConfig::new("test".into(), "test".into(), "test".into())
+                .with_ping_interval(Duration::ZERO)
+                .with_queue(1, FullQueue::Error),
+        );
+        conn.connector = Some(connector(vec![]));
+        let limited = Limited::Chat("foo".into());
+        conn.limiter.acquire(&limited, Instant::now()).unwrap();
+        conn.writer().privmsg("foo", "waiting").unwrap();
+        assert!(matches!(
+            conn.writer().privmsg("foo", "full"),
+            Err(IrcError::QueueFull)
+        ));
+        let irc = conn.next_irc().unwrap().unwrap();
+        assert!(matches!(irc.irc_type, IrcType::Ping));
+        assert_eq!(
+            "PING :tmi.twitch.tv\r\nPONG :tmi.twitch.tv\r\n",
+            MockStream::written(&conn)
+        );
+        assert_eq!(1, conn.queue_depth());
+    }
+
+    const WELCOME: &str = ":tmi.twitch.tv 001 nick :Welcome, GLHF!\r\n";
+
+    fn connector(streams: Vec<MockStream>) -> Connector<MockStream> {
+        let streams = std::sync::Mutex::new(VecDeque::from(streams));
+        Box::new(move |_| {
+            streams
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| ErrorKind::ConnectionRefused.into())
+        })
+    }
+
+    #[test]
+    fn reconnect_and_rejoin() {
+        let config = LocoConfig::new("token".into(), "nick".into(), "chan".into())
+            .with_reconnect(ReconnectPolicy::default().with_base_delay(Duration::ZERO));
+        let mut conn = LocoConnection::from_stream(None, config);
+        conn.connector = Some(connector(vec![
+            MockStream::new(&[WELCOME, ":tmi.twitch.tv RECONNECT\r\n"]),
+            MockStream::new(&[WELCOME]),
+            MockStream::new(&[WELCOME, "PING :tmi.twitch.tv\r\n"]),
+        ]));
+        conn.establish().unwrap();
+        let types = (0..7)
+            .map(|_| conn.next().unwrap().irc_type)
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            types[..],
+            [
+                IrcType::Unknown,
+                IrcType::Reconnect,
+                IrcType::Reconnected,
+                IrcType::Unknown,
+                IrcType::Reconnected,
+                IrcType::Unknown,
+                IrcType::Ping
+            ]
+        ));
+        let written = MockStream::written(&conn);
+        assert_eq!(
+            concat!(
+                "CAP REQ :twitch.tv/commands\r\nCAP REQ :twitch.tv/membership\r\n",
+                "CAP REQ :twitch.tv/tags\r\nPASS oauth:token\r\nNICK nick\r\nJOIN #chan\r\nPONG :tmi.twitch.tv\r\n"
+            ),
+            written
+        );
+    }
+
+    #[test]
+    fn wait_login_result() {
+        let config = LocoConfig::new("token".into(), "nick".into(), "chan".into())
+            .with_login_timeout(Duration::ZERO)
+            .with_reconnect(ReconnectPolicy::default().with_base_delay(Duration::ZERO));
+        let mut conn = LocoConnection::from_stream(None, config);
+        conn.connector = Some(connector(vec![
+            MockStream::new(&[":tmi.twitch.tv NOTICE * :Login authentication failed\r\n"]),
+            MockStream::new(&[WELCOME]),
+        ]));
+        assert!(matches!(conn.establish(), Err(IrcError::Authentication)));
+        assert!(conn.welcome().is_none());
+
+        conn.connector = Some(connector(vec![
+            MockStream::new(&[""]),
+            MockStream::new(&[":tmi.twitch.tv CAP * ACK :twitch.tv/tags\r\n", WELCOME]),
+        ]));
+        conn.establish().unwrap();
+        assert_eq!(
+            Some(&Welcome {
+                nickname: "nick".into(),
+                server: Some("tmi.twitch.tv".into()),
+                message: "Welcome, GLHF!".into(),
+            }),
+            conn.welcome()
+        );
+        assert_eq!(2, conn.backlog.len());
+        assert!(MockStream::written(&conn).ends_with("JOIN #chan\r\n"));
+    }
+
+    #[test]
+    fn negotiate_capabilities() {
+        let config = LocoConfig::new("token".into(), "nick".into(), "chan".into())
+            .with_capabilities([Capability::Tags, Capability::Commands]);
+        let mut conn = LocoConnection::from_stream(None, config);
+        conn.connector = Some(connector(vec![MockStream::new(&[
+            ":tmi.twitch.tv CAP * ACK :twitch.tv/tags\r\n",
+            ":tmi.twitch.tv CAP * NAK :twitch.tv/commands\r\n",
+            WELCOME,
+        ])]));
+        conn.establish().unwrap();
+        assert!(MockStream::written(&conn).starts_with(
+            "CAP REQ :twitch.tv/tags\r\nCAP REQ :twitch.tv/commands\r\nPASS oauth:token\r\n"
+        ));
+        let capabilities = conn.capabilities();
+        assert!(capabilities.is_enabled(Capability::Tags));
+        assert_eq!(
+            Some(CapabilityStatus::Rejected),
+            capabilities.status(Capability::Commands)
+        );
+        assert_eq!(None, capabilities.status(Capability::Membership));
+        assert!(matches!(conn.next().unwrap().irc_type, IrcType::CapAck));
+
+        let config =
+            LocoConfig::new("token".into(), "nick".into(), "chan".into()).with_capabilities([]);
+        assert_eq!(
+            vec!["PASS oauth:token\r\n", "NICK nick\r\n"],
+            config.login_commands()
+        );
+    }
+
+    #[test]
+    fn give_up_after_max_attempts() {
+        let config = LocoConfig::new("token".into(), "nick".into(), "chan".into())
+            .with_reconnect(ReconnectPolicy::new(2).with_base_delay(Duration::ZERO));
+        let mut conn = LocoConnection::from_stream(None, config);
+        conn.connector = Some(connector(vec![]));
+        let err = conn.establish().unwrap_err();
+        assert!(matches!(&err, IrcError::MaxAttemps(last) if matches!(**last, IrcError::Io(_))));
+        assert_eq!(
+            ErrorKind::ConnectionRefused,
+            err.source()
+                .and_then(|last| last.source())
+                .and_then(|io| io.downcast_ref::<std::io::Error>())
+                .unwrap()
+                .kind()
+        );
+    }
+
+    #[test]
+    fn track_joined_channels() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[
+                ":nick!nick@nick.tmi.twitch.tv JOIN #foo\r\n",
+                ":other!other@other.tmi.twitch.tv JOIN #foo\r\n",
+                ":nick!nick@nick.tmi.twitch.tv JOIN #bar\r\n",
+                ":nick!nick@nick.tmi.twitch.tv PART #foo\r\n",
+            ])),
+            LocoConfig::new("token".into(), "Nick".into(), "#Foo".into()).with_channels(["bar"]),
+        );
+        assert_eq!(
+            vec!["bar", "foo"],
+            conn.wanted_channels.iter().collect::<Vec<_>>()
+        );
+        conn.join("#Baz").unwrap();
+        conn.part("foo").unwrap();
+        conn.privmsg("#bar", "hello").unwrap();
+        for _ in 0..2 {
+            conn.next().unwrap();
+        }
+        assert_eq!(vec!["foo"], conn.channels().collect::<Vec<_>>());
+        for _ in 0..2 {
+            conn.next().unwrap();
+        }
+        assert_eq!(vec!["bar"], conn.channels().collect::<Vec<_>>());
+        assert_eq!(
+            vec!["bar", "baz"],
+            conn.wanted_channels.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "JOIN #baz\r\nPART #foo\r\nPRIVMSG #bar :hello\r\n",
+            MockStream::written(&conn)
+        );
+    }
+
+    #[test]
+    fn track_room_state() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[
+                "@emote-only=0;followers-only=-1;r9k=0;room-id=1;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #foo\r\n",
+                "@room-id=1;slow=30 :tmi.twitch.tv ROOMSTATE #foo\r\n",
+                ":nick!nick@nick.tmi.twitch.tv PART #foo\r\n",
+            ])),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        conn.next().unwrap();
+        assert!(!conn.room_state("#foo").unwrap().is_slow());
+        conn.next().unwrap();
+        let state = conn.room_state("Foo").unwrap();
+        assert_eq!(Some(Duration::from_secs(30)), state.slow);
+        assert_eq!(Some(false), state.subs_only);
+        conn.next().unwrap();
+        assert!(conn.room_state("foo").is_none());
+    }
+
+    #[test]
+    fn track_user_state() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[
+                "@badges=;color=#FF0000;display-name=Nick;emote-sets=0;user-id=42;user-type= :tmi.twitch.tv GLOBALUSERSTATE\r\n",
+                "@badges=moderator/1;display-name=Nick;emote-sets=0;mod=1;subscriber=0;user-type=mod :tmi.twitch.tv USERSTATE #foo\r\n",
+                ":nick!nick@nick.tmi.twitch.tv PART #foo\r\n",
+            ])),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        conn.next().unwrap();
+        let global = conn.global_user_state().unwrap();
+        assert_eq!(Some("42"), global.user_id.as_deref());
+        assert!(!conn.is_moderator("foo"));
+        conn.next().unwrap();
+        assert!(conn.is_moderator("#Foo"));
+        assert_eq!(
+            Some("Nick"),
+            conn.user_state("foo").unwrap().display_name.as_deref()
+        );
+        let now = Instant::now();
+        assert_eq!(
+            Ok(()),
+            conn.limiter.acquire(&Limited::Chat("foo".into()), now)
+        );
+        assert_eq!(
+            Ok(()),
+            conn.limiter.acquire(&Limited::Chat("foo".into()), now)
+        );
+        conn.next().unwrap();
+        assert!(conn.user_state("foo").is_none());
+        assert!(conn
+            .limiter
+            .acquire(&Limited::Chat("foo".into()), now)
+            .is_ok());
+        assert!(conn
+            .limiter
+            .acquire(&Limited::Chat("foo".into()), now)
+            .is_err());
+    }
+
+    #[test]
+    fn local_server_with_timeouts() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let login = lines.next().unwrap().unwrap();
+            stream.write_all(WELCOME.as_bytes()).unwrap();
+            stream
+                .write_all(b":foo!foo@foo.tmi.twitch.tv PRIVMSG #chan :hello\r\n")
+                .unwrap();
+            (stream, login)
+        });
+        let config = LocoConfig::new("token".into(), "nick".into(), "chan".into())
+            .with_host("127.0.0.1".into())
+            .with_port(port)
+            .with_connect_timeout(Duration::from_secs(1))
+            .with_read_timeout(Duration::from_millis(300))
+            .with_write_timeout(Duration::from_secs(1))
+            .with_reconnect(ReconnectPolicy::none());
+        let mut conn = LocoConnection::new(config).unwrap();
+        assert_eq!("nick", conn.welcome().unwrap().nickname);
+        assert!(matches!(
+            conn.next_irc().unwrap().unwrap().irc_type,
+            IrcType::Unknown
+        ));
+        let irc = conn.next_irc().unwrap().unwrap();
+        assert_eq!(Some("hello".into()), irc.message);
+        let (_stream, login) = server.join().unwrap();
+        assert_eq!("CAP REQ :twitch.tv/commands", login);
+        assert!(matches!(conn.next_irc(), Err(IrcError::Timeout)));
+    }
+
+    #[test]
+    fn anonymous_cannot_chat() {
+        let mut conn = LocoConnection::from_stream(None, LocoConfig::anonymous("foo".into()));
+        conn.connection = Some(MockStream::new(&[WELCOME]));
+        conn.login().unwrap();
+        assert!(matches!(
+            conn.privmsg("foo", "hello"),
+            Err(IrcError::Anonymous)
+        ));
+        assert!(matches!(
+            conn.send_command(Command::Privmsg, "hello"),
+            Err(IrcError::Anonymous)
+        ));
+        conn.join("bar").unwrap();
+        let written = MockStream::written(&conn);
+        assert!(written.contains("\r\nNICK justinfan"));
+        assert!(!written.contains("PASS"));
+        assert!(!written.contains("PRIVMSG"));
+        assert!(written.ends_with("JOIN #bar\r\n"));
+    }
+
+    #[test]
+    fn reject_injected_commands() {
+        let mut conn = LocoConnection::from_stream(
+            None,
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        conn.connection = Some(MockStream::new(&[WELCOME]));
+        conn.login().unwrap();
+        assert!(matches!(
+            conn.privmsg("foo", "hi\r\nQUIT"),
+            Err(IrcError::LineBreak)
+        ));
+        assert!(matches!(
+            conn.send_command(Command::Privmsg, "hi\nPASS oauth:other"),
+            Err(IrcError::LineBreak)
+        ));
+        assert!(matches!(
+            conn.privmsg("foo :x\r\nJOIN #bar", "hi"),
+            Err(IrcError::InvalidChannel(_))
+        ));
+        assert!(matches!(
+            conn.join("bar baz"),
+            Err(IrcError::InvalidChannel(_))
+        ));
+        assert!(matches!(
+            conn.writer().part("bar\r\nQUIT"),
+            Err(IrcError::InvalidChannel(_))
+        ));
+        conn.privmsg("#Foo", "hello").unwrap();
+        conn.send_command(Command::Part, "#Foo").unwrap();
+        let written = MockStream::written(&conn);
+        assert!(!written.contains("QUIT") && !written.contains("bar"));
+        assert!(written.ends_with("JOIN #foo\r\nPRIVMSG #foo :hello\r\nPART #foo\r\n"));
+
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[WELCOME])),
+            LocoConfig::new(
+                "token".into(),
+                "nick".into(),
+                "foo\r\nPRIVMSG #x :spam".into(),
+            ),
+        );
+        assert!(matches!(
+            conn.login(),
+            Err(IrcError::Config(ConfigError::InvalidChannel(_)))
+        ));
+        assert!(MockStream::written(&conn).is_empty());
+    }
+
+    #[test]
+    fn write_from_other_thread() {
+        fn is_thread_safe<S: Send + Sync>() {}
+        fn is_send<S: Send>() {}
+        is_thread_safe::<LocoWriter>();
+        is_send::<LocoConnection<TcpStream>>();
+        is_send::<LocoReader<TcpStream>>();
+
+        let conn = LocoConnection::from_stream(
+            Some(MockStream::new(&["", "PING :tmi.twitch.tv\r\n"])),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        let (mut reader, writer) = conn.split();
+        let sender = writer.clone();
+        thread::spawn(move || {
+            sender.privmsg("#foo", "hello").unwrap();
+            sender.join("bar").unwrap();
+        })
+        .join()
+        .unwrap();
+        assert!(matches!(reader.next().unwrap().irc_type, IrcType::Ping));
+        assert_eq!(
+            "PRIVMSG #foo :hello\r\nJOIN #bar\r\nPONG :tmi.twitch.tv\r\n",
+            MockStream::written(&reader.connection)
+        );
+        drop(reader);
+        assert!(matches!(
+            writer.privmsg("foo", "bye"),
+            Err(IrcError::Closed)
+        ));
+    }
+
+    #[test]
+    fn graceful_shutdown() {
+        let stream = MockStream::new(&[
+            ":nick!nick@nick.tmi.twitch.tv JOIN #foo\r\n",
+            "",
+            "PING :tmi.twitch.tv\r\n",
+        ]);
+        let output = stream.output.clone();
+        let mut conn = LocoConnection::from_stream(
+            Some(stream),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        let writer = conn.writer();
+        let handle = conn.shutdown_handle();
+        let mut events = 0;
+        let status = conn.read(|_| {
+            events += 1;
+            writer.privmsg("foo", "bye").unwrap();
+            handle.shutdown();
+        });
+        assert_eq!(ReadStatus::Shutdown, status.unwrap());
+        assert_eq!(1, events);
+        assert_eq!(
+            "PRIVMSG #foo :bye\r\nPART #foo\r\nQUIT\r\n",
+            MockStream::output(&output)
+        );
+        assert!(conn.next().is_none());
+        assert!(matches!(
+            writer.privmsg("foo", "late"),
+            Err(IrcError::Closed)
+        ));
+    }
+
+    #[test]
+    fn queue_over_rate_limit() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[])),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        conn.privmsg("foo", "first").unwrap();
+        conn.privmsg("foo", "second").unwrap();
+        conn.privmsg("bar", "other").unwrap();
+        conn.send_command(Command::Pong, "").unwrap();
+        assert_eq!(
+            "PRIVMSG #foo :first\r\nPRIVMSG #bar :other\r\nPONG :tmi.twitch.tv\r\n",
+            MockStream::written(&conn)
+        );
+        assert_eq!(1, conn.queue_depth());
+        assert_eq!(vec!["PRIVMSG #foo :second\r\n"], conn.drain_queue());
+        assert_eq!(0, conn.writer().queue_depth());
+    }
+
+    #[test]
+    fn shutdown_within_rate_limit() {
+        let mut conn = LocoConnection::from_stream(
+            Some(MockStream::new(&[])),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into()),
+        );
+        let writer = conn.writer();
+        for text in ["first", "second", "third"] {
+            writer.privmsg("foo", text).unwrap();
         }
+        let output = conn.connection.as_ref().unwrap().output.clone();
+        conn.shutdown().unwrap();
+        assert_eq!(
+            "PRIVMSG #foo :first\r\nQUIT\r\n",
+            MockStream::output(&output)
+        );
+        assert_eq!(
+            vec!["PRIVMSG #foo :second\r\n", "PRIVMSG #foo :third\r\n"],
+            conn.drain_queue()
+        );
+    }
+
+    #[test]
+    fn fill_queue_while_reading() {
+        let stream = MockStream::new(&[
+            ":bar!bar@bar.tmi.twitch.tv PRIVMSG #foo :hi\r\n",
+            "PING :tmi.twitch.tv\r\n",
+        ]);
+        let output = stream.output.clone();
+        let mut conn = LocoConnection::from_stream(
+            Some(stream),
+            LocoConfig::new("token".into(), "nick".into(), "foo".into())
+                .with_queue(2, FullQueue::Block),
+        );
+        let writer = conn.writer();
+        let mut results = Vec::new();
+        let status = conn.read(|irc| {
+            if let IrcType::Message = irc.irc_type {
+                for text in ["a", "b", "c"] {
+                    results.push(writer.privmsg("foo", text));
+                }
+            }
+        });
+        assert_eq!(ReadStatus::Closed, status.unwrap());
+        assert!(matches!(
+            results[..],
+            [Ok(()), Ok(()), Err(IrcError::QueueFull)]
+        ));
+        assert_eq!(
+            "PRIVMSG #foo :a\r\nPONG :tmi.twitch.tv\r\n",
+            MockStream::output(&output)
+        );
+        conn.privmsg("foo", "d").unwrap();
+        assert!(matches!(conn.privmsg("foo", "e"), Err(IrcError::QueueFull)));
+        assert_eq!(2, conn.queue_depth());
     }
 }
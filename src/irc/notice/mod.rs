@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use super::Irc;
+
+/// NOTICE decoded by its `msg-id`, most of the `msg_*` ids reject a chat message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoticeKind {
+    /// Wrong oauth token or nickname, sent without `msg-id`
+    Authentication,
+    /// The oauth token is not valid, sent without `msg-id`
+    ImproperOAuth,
+    /// The account is banned in the channel
+    Banned,
+    /// The message has characters that are not allowed
+    BadCharacters,
+    /// The channel blocked the account, `msg_channel_blocked`
+    ChannelBlocked,
+    /// The channel was suspended, the message was not sent
+    ChannelSuspended,
+    /// Same message sent in the last 30 seconds
+    Duplicate,
+    /// Only emotes are allowed in the channel
+    EmoteOnly,
+    /// Only followers can chat, also for not following long enough
+    FollowersOnly,
+    /// Unique chat mode, the message is not unique
+    R9k,
+    /// Sending messages too quickly
+    RateLimited,
+    /// Rejected by AutoMod or by the channel moderation settings
+    Rejected,
+    /// Slow mode, `wait` is parsed from the text when it has the seconds left
+    SlowMode { wait: Option<Duration> },
+    /// Only subscribers can chat
+    SubsOnly,
+    /// The account was suspended
+    Suspended,
+    /// The account is timed out in the channel for `remaining`
+    TimedOut { remaining: Option<Duration> },
+    /// The channel requires a verified email
+    VerifiedEmail,
+    /// The channel requires a verified phone number
+    VerifiedPhoneNumber,
+    /// Chat mode changed by a moderator, like `slow_on` or `subs_off`
+    ModeChanged { mode: ChatMode, enabled: bool },
+    /// Command not recognized, like `/foo`
+    UnrecognizedCommand,
+    /// No permission to run the command
+    NoPermission,
+    /// Other `msg-id`, empty when the notice has none
+    Other(String),
+}
+
+/// Chat mode of a `ModeChanged` notice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatMode {
+    EmoteOnly,
+    FollowersOnly,
+    R9k,
+    Slow,
+    SubsOnly,
+}
+
+impl NoticeKind {
+    pub(super) fn from_irc(irc: &Irc) -> Self {
+        let message = irc.message.as_deref().unwrap_or_default();
+        let mode = |mode, enabled| Self::ModeChanged { mode, enabled };
+        match irc.tag("msg-id").unwrap_or_default() {
+            "" if message.starts_with("Login authentication failed") => Self::Authentication,
+            "" if message.starts_with("Improperly formatted auth") => Self::ImproperOAuth,
+            "msg_banned" => Self::Banned,
+            "msg_bad_characters" => Self::BadCharacters,
+            "msg_channel_blocked" => Self::ChannelBlocked,
+            "msg_channel_suspended" => Self::ChannelSuspended,
+            "msg_duplicate" => Self::Duplicate,
+            "msg_emoteonly" => Self::EmoteOnly,
+            "msg_followersonly" | "msg_followersonly_followed" | "msg_followersonly_zero" => {
+                Self::FollowersOnly
+            }
+            "msg_r9k" => Self::R9k,
+            "msg_ratelimit" => Self::RateLimited,
+            "msg_rejected" | "msg_rejected_mandatory" => Self::Rejected,
+            "msg_slowmode" => Self::SlowMode {
+                wait: seconds(message),
+            },
+            "msg_subsonly" => Self::SubsOnly,
+            "msg_suspended" => Self::Suspended,
+            "msg_timedout" => Self::TimedOut {
+                remaining: seconds(message),
+            },
+            "msg_verified_email" => Self::VerifiedEmail,
+            "msg_requires_verified_phone_number" => Self::VerifiedPhoneNumber,
+            "emote_only_on" => mode(ChatMode::EmoteOnly, true),
+            "emote_only_off" => mode(ChatMode::EmoteOnly, false),
+            "followers_on" | "followers_on_zero" => mode(ChatMode::FollowersOnly, true),
+            "followers_off" => mode(ChatMode::FollowersOnly, false),
+            "r9k_on" => mode(ChatMode::R9k, true),
+            "r9k_off" => mode(ChatMode::R9k, false),
+            "slow_on" => mode(ChatMode::Slow, true),
+            "slow_off" => mode(ChatMode::Slow, false),
+            "subs_on" => mode(ChatMode::SubsOnly, true),
+            "subs_off" => mode(ChatMode::SubsOnly, false),
+            "unrecognized_cmd" => Self::UnrecognizedCommand,
+            "no_permission" => Self::NoPermission,
+            other => Self::Other(other.into()),
+        }
+    }
+
+    /// The chat message was not sent, includes other `msg_*` ids
+    pub fn is_rejection(&self) -> bool {
+        match self {
+            Self::Authentication
+            | Self::ImproperOAuth
+            | Self::ModeChanged { .. }
+            | Self::UnrecognizedCommand
+            | Self::NoPermission => false,
+            Self::Other(msg_id) => msg_id.starts_with("msg_"),
+            _ => true,
+        }
+    }
+
+    /// Sending the message again later can work, the time to wait when it is known
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::SlowMode { wait } => Some(wait.unwrap_or(Duration::from_secs(1))),
+            Self::TimedOut { remaining } => *remaining,
+            Self::RateLimited | Self::Duplicate => Some(Duration::from_secs(30)),
+            _ => None,
+        }
+    }
+}
+
+/// Last number before "seconds" in the text of a notice, like "for 540 more seconds"
+fn seconds(message: &str) -> Option<Duration> {
+    let mut number = None;
+    for word in message.split_whitespace() {
+        if word.starts_with("second") {
+            return number.map(Duration::from_secs);
+        }
+        number = word.parse().ok().or(number);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::parser::Parser;
+
+    fn kind(line: &str) -> NoticeKind {
+        NoticeKind::from_irc(&Parser.parse(line.into()).unwrap())
+    }
+
+    #[test]
+    fn decode_notices() {
+        assert_eq!(
+            NoticeKind::Authentication,
+            kind(":tmi.twitch.tv NOTICE * :Login authentication failed")
+        );
+        let slow = kind(
+            "@msg-id=msg_slowmode :tmi.twitch.tv NOTICE #foo :This room is in slow mode and you are sending messages too quickly. You will be able to talk again in 12 seconds.",
+        );
+        assert_eq!(
+            NoticeKind::SlowMode {
+                wait: Some(Duration::from_secs(12))
+            },
+            slow
+        );
+        assert!(slow.is_rejection());
+        assert_eq!(Some(Duration::from_secs(12)), slow.retry_after());
+        assert_eq!(
+            NoticeKind::TimedOut {
+                remaining: Some(Duration::from_secs(540))
+            },
+            kind("@msg-id=msg_timedout :tmi.twitch.tv NOTICE #foo :You are timed out for 540 more seconds.")
+        );
+        let phone = kind("@msg-id=msg_requires_verified_phone_number :tmi.twitch.tv NOTICE #foo :A verified phone number is required to chat in this channel.");
+        assert_eq!(NoticeKind::VerifiedPhoneNumber, phone);
+        assert_eq!(None, phone.retry_after());
+        let mode = kind("@msg-id=subs_on :tmi.twitch.tv NOTICE #foo :This room is now in subscribers-only mode.");
+        assert_eq!(
+            NoticeKind::ModeChanged {
+                mode: ChatMode::SubsOnly,
+                enabled: true
+            },
+            mode
+        );
+        assert!(!mode.is_rejection());
+        let other = kind("@msg-id=msg_new_reason :tmi.twitch.tv NOTICE #foo :Nope.");
+        assert_eq!(NoticeKind::Other("msg_new_reason".into()), other);
+        assert!(other.is_rejection());
+    }
+}
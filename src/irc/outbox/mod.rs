@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use super::{IrcError, RawMessage};
+
+/// Twitch rejects a message identical to the previous one in the channel in this period
+const DUPLICATE_PERIOD: Duration = Duration::from_secs(30);
+/// Appended to a repeated message to make it different, it is not displayed by Twitch
+const DUPLICATE_SUFFIX: &str = " \u{E0000}";
+const MODERATION_COMMANDS: &[&str] = &[
+    "/ban",
+    "/unban",
+    "/timeout",
+    "/untimeout",
+    "/delete",
+    "/clear",
+    "/slow",
+    "/slowoff",
+    "/followers",
+    "/followersoff",
+    "/subscribers",
+    "/subscribersoff",
+    "/emoteonly",
+    "/emoteonlyoff",
+    "/uniquechat",
+    "/uniquechatoff",
+];
+
+/// What to do with a new command when the outgoing queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FullQueue {
+    /// Wait until the connection writes the queued commands, the thread reading
+    /// the connection can not wait for itself and gets `IrcError::QueueFull`
+    #[default]
+    Block,
+    /// Discard the new command silently
+    Discard,
+    /// Return `IrcError::QueueFull`
+    Error,
+}
+
+/// Commands waiting to be written, shared between the connection and its writers.
+/// PING, PONG and moderation commands are written before the chat and are not counted
+/// in the capacity
+#[derive(Clone)]
+pub(super) struct Outbox {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    space: Condvar,
+    closed: AtomicBool,
+    capacity: usize,
+    when_full: FullQueue,
+}
+
+#[derive(Default)]
+struct Queue {
+    urgent: VecDeque<String>,
+    normal: VecDeque<String>,
+    /// Last chat message queued in each channel and when
+    last_chat: HashMap<String, (String, Instant)>,
+    /// Thread reading the connection, the only one making space in the queue
+    reader: Option<ThreadId>,
+}
+
+impl Outbox {
+    pub(super) fn new(capacity: usize, when_full: FullQueue) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                queue: Mutex::default(),
+                space: Condvar::new(),
+                closed: AtomicBool::new(false),
+                capacity,
+                when_full,
+            }),
+        }
+    }
+
+    /// Queue a command, with `FullQueue::Block` waits for space only when `block` is set
+    /// and it is not the reading thread, otherwise returns `IrcError::QueueFull`
+    /// like `FullQueue::Error`
+    pub(super) fn push(&self, command: String, block: bool) -> Result<(), IrcError> {
+        let urgent = is_urgent(&command);
+        let mut queue = self.lock();
+        let block = block && queue.reader != Some(thread::current().id());
+        loop {
+            if self.shared.closed.load(Ordering::Acquire) {
+                return Err(IrcError::Closed);
+            }
+            if urgent || queue.normal.len() < self.shared.capacity {
+                break;
+            }
+            match self.shared.when_full {
+                FullQueue::Block if block => {
+                    queue = self
+                        .shared
+                        .space
+                        .wait(queue)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+                FullQueue::Discard => return Ok(()),
+                FullQueue::Block | FullQueue::Error => return Err(IrcError::QueueFull),
+            }
+        }
+        if urgent {
+            queue.urgent.push_back(command);
+        } else {
+            let command = queue.deduplicate(command, Instant::now());
+            queue.normal.push_back(command);
+        }
+        Ok(())
+    }
+
+    /// Remove all the queued commands, the urgent ones first
+    pub(super) fn take(&self) -> VecDeque<String> {
+        let mut queue = self.lock();
+        let mut commands = std::mem::take(&mut queue.urgent);
+        commands.append(&mut queue.normal);
+        self.shared.space.notify_all();
+        commands
+    }
+
+    /// Put back commands that could not be sent yet, before the new ones
+    pub(super) fn requeue(&self, commands: VecDeque<String>) {
+        if commands.is_empty() {
+            return;
+        }
+        let mut queue = self.lock();
+        for command in commands.into_iter().rev() {
+            if is_urgent(&command) {
+                queue.urgent.push_front(command);
+            } else {
+                queue.normal.push_front(command);
+            }
+        }
+    }
+
+    /// The current thread reads the connection, writers in it do not wait for space
+    pub(super) fn set_reader(&self) {
+        self.lock().reader = Some(thread::current().id());
+    }
+
+    /// Number of commands waiting to be written
+    pub(super) fn depth(&self) -> usize {
+        let queue = self.lock();
+        queue.urgent.len() + queue.normal.len()
+    }
+
+    pub(super) fn when_full(&self) -> FullQueue {
+        self.shared.when_full
+    }
+
+    /// The connection is gone, new commands are rejected and blocked writers wake up
+    pub(super) fn close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        let _queue = self.lock();
+        self.shared.space.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.shared
+            .queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Queue {
+    /// Make a chat message different from the previous one in the channel if they are equal
+    fn deduplicate(&mut self, command: String, now: Instant) -> String {
+        let (channel, text) = match RawMessage::parse(&command) {
+            Some(raw) if raw.command == "PRIVMSG" => match (raw.channel(), raw.trailing) {
+                (Some(channel), Some(text)) if !text.starts_with('/') => {
+                    (channel.to_owned(), text.to_owned())
+                }
+                _ => return command,
+            },
+            _ => return command,
+        };
+        let duplicate = self
+            .last_chat
+            .get(&channel)
+            .is_some_and(|(last, at)| *last == text && now.duration_since(*at) < DUPLICATE_PERIOD);
+        let (command, text) = if duplicate {
+            let line = command.trim_end_matches(['\r', '\n']);
+            (
+                format!("{line}{DUPLICATE_SUFFIX}\r\n"),
+                format!("{text}{DUPLICATE_SUFFIX}"),
+            )
+        } else {
+            (command, text)
+        };
+        self.last_chat.insert(channel, (text, now));
+        command
+    }
+}
+
+/// PONG and moderation commands skip the chat messages in the queue
+fn is_urgent(command: &str) -> bool {
+    match RawMessage::parse(command) {
+        Some(raw) if matches!(raw.command, "PING" | "PONG") => true,
+        Some(raw) if raw.command == "PRIVMSG" => raw.trailing.is_some_and(|text| {
+            let name = text.split(' ').next().unwrap_or_default();
+            MODERATION_COMMANDS.contains(&name)
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn priorities_and_duplicates() {
+        let outbox = Outbox::new(2, FullQueue::Error);
+        outbox.push("PRIVMSG #foo :hi\r\n".into(), true).unwrap();
+        outbox.push("PRIVMSG #foo :hi\r\n".into(), true).unwrap();
+        outbox
+            .push("PRIVMSG #foo :/timeout bar 10\r\n".into(), true)
+            .unwrap();
+        outbox.push("PONG :tmi.twitch.tv\r\n".into(), true).unwrap();
+        assert!(matches!(
+            outbox.push("PRIVMSG #foo :hi\r\n".into(), true),
+            Err(IrcError::QueueFull)
+        ));
+        assert_eq!(4, outbox.depth());
+        assert_eq!(
+            vec![
+                "PRIVMSG #foo :/timeout bar 10\r\n",
+                "PONG :tmi.twitch.tv\r\n",
+                "PRIVMSG #foo :hi\r\n",
+                "PRIVMSG #foo :hi \u{E0000}\r\n",
+            ],
+            Vec::from(outbox.take())
+        );
+        outbox.push("PRIVMSG #foo :hi\r\n".into(), true).unwrap();
+        outbox.push("PRIVMSG #bar :hi\r\n".into(), true).unwrap();
+        assert_eq!(
+            vec!["PRIVMSG #foo :hi\r\n", "PRIVMSG #bar :hi\r\n"],
+            Vec::from(outbox.take())
+        );
+    }
+
+    #[test]
+    fn full_queue_policies() {
+        let outbox = Outbox::new(1, FullQueue::Discard);
+        outbox.push("PRIVMSG #foo :a\r\n".into(), true).unwrap();
+        outbox.push("PRIVMSG #foo :b\r\n".into(), true).unwrap();
+        assert_eq!(vec!["PRIVMSG #foo :a\r\n"], Vec::from(outbox.take()));
+
+        let outbox = Outbox::new(1, FullQueue::Block);
+        outbox.push("PRIVMSG #foo :a\r\n".into(), true).unwrap();
+        assert!(matches!(
+            outbox.push("PRIVMSG #foo :b\r\n".into(), false),
+            Err(IrcError::QueueFull)
+        ));
+        let writer = outbox.clone();
+        let blocked = thread::spawn(move || writer.push("PRIVMSG #foo :b\r\n".into(), true));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(vec!["PRIVMSG #foo :a\r\n"], Vec::from(outbox.take()));
+        blocked.join().unwrap().unwrap();
+        assert_eq!(vec!["PRIVMSG #foo :b\r\n"], Vec::from(outbox.take()));
+
+        outbox.push("PRIVMSG #foo :c\r\n".into(), true).unwrap();
+        let writer = outbox.clone();
+        let blocked = thread::spawn(move || writer.push("PRIVMSG #foo :d\r\n".into(), true));
+        thread::sleep(Duration::from_millis(20));
+        outbox.close();
+        assert!(matches!(blocked.join().unwrap(), Err(IrcError::Closed)));
+    }
+}
@@ -1,74 +1,288 @@
-use std::collections::HashMap;
-
-use fancy_regex::Regex;
-
-use super::{Irc, IrcResult, IrcType};
+use super::{tags, Irc, IrcError, IrcResult, IrcType, DEFAULT_NONE};
 
 pub(super) struct Parser;
 
-const DEFAULT_NONE: &str = "none";
+/// Source of a message, `nick!user@host` for users or only the server name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prefix<'a> {
+    /// Nickname of the user or server name
+    pub nick: &'a str,
+    pub user: Option<&'a str>,
+    pub host: Option<&'a str>,
+}
 
-impl Parser {
-    pub(super) fn parse(&self, input: String) -> IrcResult {
-        let irc_type = self.extract_event(&input);
-        let channel = if let Some(channel) = self.extract_channel(&input) {
-            channel
-        } else {
-            DEFAULT_NONE.into()
+/// IRCv3 line split in its components, borrowing from the original line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawMessage<'a> {
+    /// Tags without the leading `@`, still escaped
+    pub tags: Option<&'a str>,
+    pub prefix: Option<Prefix<'a>>,
+    /// Command name or three digit numeric reply
+    pub command: &'a str,
+    /// Middle parameters
+    pub params: Vec<&'a str>,
+    /// Last parameter, the one after ` :`
+    pub trailing: Option<&'a str>,
+}
+
+impl<'a> Prefix<'a> {
+    fn parse(input: &'a str) -> Self {
+        let (rest, host) = match input.split_once('@') {
+            Some((rest, host)) => (rest, Some(host)),
+            None => (input, None),
         };
-        let nickname = self.extract_nickname(&input, &irc_type);
-        let (message, keys) = if let IrcType::Message = irc_type {
-            let message = self.extract_message(&input);
-            let keys = self.extract_keys(&input);
-            (message, keys)
-        } else {
-            (None, None)
+        let (nick, user) = match rest.split_once('!') {
+            Some((nick, user)) => (nick, Some(user)),
+            None => (rest, None),
         };
-        Ok(Irc::new(irc_type, nickname, keys, channel, message))
+        Self { nick, user, host }
     }
+}
 
-    fn extract_event(&self, input: &str) -> IrcType {
-        let regex = Regex::new(r"((?<=\s)([A-Z]+))|(([A-Z]+)(?=\s))").unwrap();
-        if let Some(value) = self.match_regex_string(regex, input, 0) {
-            value.into()
-        } else {
-            IrcType::Unknown
+impl<'a> RawMessage<'a> {
+    /// Tokenize a single line without the `\r\n` terminator,
+    /// returns `None` when there is no command
+    pub fn parse(line: &'a str) -> Option<Self> {
+        Self::tokenize(line).ok()
+    }
+
+    /// Like `parse`, the error is the reason the line is invalid
+    fn tokenize(line: &'a str) -> Result<Self, &'static str> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        let tags = match rest.strip_prefix('@') {
+            Some(stripped) => {
+                let (tags, remaining) = stripped.split_once(' ').ok_or("tags without command")?;
+                rest = remaining;
+                Some(tags)
+            }
+            None => None,
+        };
+        rest = rest.trim_start_matches(' ');
+        let prefix = match rest.strip_prefix(':') {
+            Some(stripped) => {
+                let (prefix, remaining) =
+                    stripped.split_once(' ').ok_or("prefix without command")?;
+                rest = remaining;
+                Some(Prefix::parse(prefix))
+            }
+            None => None,
+        };
+        rest = rest.trim_start_matches(' ');
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return Err("missing command");
         }
+        let mut params = Vec::new();
+        let mut trailing = None;
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(value) = rest.strip_prefix(':') {
+                trailing = Some(value);
+                break;
+            }
+            match rest.split_once(' ') {
+                Some((param, remaining)) => {
+                    params.push(param);
+                    rest = remaining;
+                }
+                None => {
+                    params.push(rest);
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            tags,
+            prefix,
+            command,
+            params,
+            trailing,
+        })
     }
 
-    fn extract_channel(&self, input: &str) -> Option<String> {
-        let regex = Regex::new(r"(?<=\s#)(\w+)").unwrap();
-        self.match_regex_string(regex, input, 0)
+    /// Iterate over the tags as `(key, value)`, a tag without `=` has an empty value
+    pub fn tags(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.tags
+            .into_iter()
+            .flat_map(|tags| tags.split(';'))
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.split_once('=').unwrap_or((tag, "")))
     }
 
-    fn extract_nickname(&self, input: &str, irc_type: &IrcType) -> Option<String> {
-        let regex = irc_type.display_name();
-        self.match_regex_string(regex, input, 0)
+    /// Value of a single tag
+    pub fn tag(&self, key: &str) -> Option<&'a str> {
+        self.tags().find(|(k, _)| *k == key).map(|(_, value)| value)
     }
 
-    fn extract_message(&self, input: &str) -> Option<String> {
-        let regex = Regex::new(r"(?<=PRIVMSG\s#)(.+?)(?<=\w\s:)(.+)").unwrap();
-        self.match_regex_string(regex, input, 2)
+    /// First channel target without the leading `#`, the trailing parameter is only
+    /// used by JOIN and PART like `JOIN :#chan`
+    pub fn channel(&self) -> Option<&'a str> {
+        let trailing = self
+            .trailing
+            .filter(|_| matches!(self.command, "JOIN" | "PART"))
+            .filter(|trailing| !trailing.contains(' '));
+        self.params
+            .iter()
+            .copied()
+            .chain(trailing)
+            .find_map(|param| param.strip_prefix('#'))
     }
 
-    fn match_regex_string(&self, regex: Regex, input: &str, idx: usize) -> Option<String> {
-        match regex.captures(input) {
-            Ok(Some(cap)) => cap.get(idx).map(|m| m.as_str().into()),
+    /// Nickname from the prefix
+    pub fn nick(&self) -> Option<&'a str> {
+        self.prefix.as_ref().map(|prefix| prefix.nick)
+    }
+}
+
+impl Parser {
+    pub(super) fn parse(&self, input: String) -> IrcResult {
+        let raw = RawMessage::tokenize(&input).map_err(|reason| IrcError::Parse {
+            line: input.clone(),
+            reason: reason.into(),
+        })?;
+        let irc_type = match (raw.command, raw.params.get(1).copied()) {
+            ("CAP", Some("ACK")) => IrcType::CapAck,
+            ("CAP", Some("NAK")) => IrcType::CapNak,
+            (command, _) => IrcType::from(command),
+        };
+        let channel = raw.channel().unwrap_or(DEFAULT_NONE).to_owned();
+        let nickname = self.extract_nickname(&raw, &irc_type);
+        let message = match irc_type {
+            IrcType::Message
+            | IrcType::Notice
+            | IrcType::CapAck
+            | IrcType::CapNak
+            | IrcType::Usernotice
+            | IrcType::ClearMsg
+            | IrcType::HostTarget
+            | IrcType::Whisper => raw.trailing.map(str::to_owned),
             _ => None,
-        }
+        };
+        let keys = raw.tags.map(|_| {
+            raw.tags()
+                .map(|(key, value)| (key.to_owned(), tags::unescape(value).into_owned()))
+                .collect()
+        });
+        Ok(Irc::new(irc_type, nickname, keys, channel, message))
     }
 
-    fn extract_keys(&self, input: &str) -> Option<HashMap<String, String>> {
-        let key_pair = input.split(" :").next().unwrap();
-        let mut key_value = HashMap::new();
-        for key_pair in key_pair.split(';') {
-            let mut key_value_iter = key_pair.split('=');
-            let key = key_value_iter.next().unwrap();
-            let value = key_value_iter.next().unwrap();
-            key_value.insert(key.into(), value.into());
-        }
-        Some(key_value)
+    fn extract_nickname(&self, raw: &RawMessage, irc_type: &IrcType) -> Option<String> {
+        let nickname = match irc_type {
+            IrcType::Message | IrcType::Join | IrcType::Part | IrcType::Whisper => raw.nick(),
+            IrcType::Usernotice | IrcType::UserState | IrcType::GlobalUserState => {
+                raw.tag("display-name")
+            }
+            IrcType::CleanChat => raw.trailing,
+            IrcType::ClearMsg => raw.tag("login"),
+            _ => None,
+        }?;
+        (!nickname.is_empty()).then(|| nickname.to_owned())
     }
 }
 
-//TODO: WRITE A FUCKING TEST MOTHERFUCKER
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::Color;
+
+    #[test]
+    fn tokenize_line() {
+        let raw = RawMessage::parse(
+            "@badges=;color=#FF0000;display-name=Foo :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :hello : world",
+        )
+        .unwrap();
+        assert_eq!(Some("badges=;color=#FF0000;display-name=Foo"), raw.tags);
+        assert_eq!(
+            Some(Prefix {
+                nick: "foo",
+                user: Some("foo"),
+                host: Some("foo.tmi.twitch.tv"),
+            }),
+            raw.prefix
+        );
+        assert_eq!("PRIVMSG", raw.command);
+        assert_eq!(vec!["#bar"], raw.params);
+        assert_eq!(Some("hello : world"), raw.trailing);
+        assert_eq!(Some("Foo"), raw.tag("display-name"));
+        assert_eq!(Some(""), raw.tag("badges"));
+        assert_eq!(Some("bar"), raw.channel());
+
+        let channel = |line| RawMessage::parse(line).unwrap().channel();
+        assert_eq!(
+            Some("bar"),
+            channel(":foo!foo@foo.tmi.twitch.tv JOIN :#bar")
+        );
+        assert_eq!(None, channel(":tmi.twitch.tv NOTICE * :#foo is bad"));
+        assert_eq!(
+            None,
+            channel(":foo!foo@foo.tmi.twitch.tv WHISPER bar :#hey")
+        );
+        assert_eq!(None, channel(":foo!foo@foo.tmi.twitch.tv PART :#bar baz"));
+    }
+
+    #[test]
+    fn tokenize_numerics_and_server_lines() {
+        let raw = RawMessage::parse(":foo.tmi.twitch.tv 353 foo = #bar :foo baz").unwrap();
+        assert_eq!("353", raw.command);
+        assert_eq!(vec!["foo", "=", "#bar"], raw.params);
+        assert_eq!(Some("foo baz"), raw.trailing);
+
+        let raw = RawMessage::parse("PING :tmi.twitch.tv").unwrap();
+        assert_eq!(None, raw.prefix);
+        assert_eq!("PING", raw.command);
+        assert_eq!(Some("tmi.twitch.tv"), raw.trailing);
+
+        let raw = RawMessage::parse(":tmi.twitch.tv RECONNECT").unwrap();
+        assert_eq!(Some("tmi.twitch.tv"), raw.nick());
+        assert_eq!("RECONNECT", raw.command);
+        assert!(raw.params.is_empty());
+
+        assert_eq!(None, RawMessage::parse(""));
+        assert_eq!(None, RawMessage::parse("@only-tags"));
+    }
+
+    #[test]
+    fn parse_privmsg() {
+        let irc = Parser
+            .parse("@display-name=Foo;mod :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :hi there".into())
+            .unwrap();
+        assert!(matches!(irc.irc_type, IrcType::Message));
+        assert_eq!(Some("foo".into()), irc.nickname);
+        assert_eq!("bar", irc.channel);
+        assert_eq!(Some("hi there".into()), irc.message);
+        assert_eq!(Some("Foo"), irc.tag("display-name"));
+        assert_eq!(Some(""), irc.tag("mod"));
+        assert!(!irc.is_mod());
+    }
+
+    #[test]
+    fn parse_tags() {
+        let irc = Parser
+            .parse(
+                concat!(
+                    r"@badges=moderator/1,subscriber/6;bits=100;color=#0000FF;emotes=25:0-4;",
+                    r"mod=1;subscriber=1;system-msg=hello\sworld;tmi-sent-ts=1000;user-id=42 ",
+                    ":foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :Kappa cheer100"
+                )
+                .into(),
+            )
+            .unwrap();
+        assert_eq!(2, irc.badges().len());
+        assert_eq!("moderator", irc.badges()[0].name);
+        assert_eq!(Some(100), irc.bits());
+        assert_eq!(Some(Color { r: 0, g: 0, b: 255 }), irc.color());
+        assert_eq!(vec![0..=4], irc.emotes()[0].ranges);
+        assert!(irc.is_mod());
+        assert!(irc.is_subscriber());
+        assert_eq!(Some("hello world"), irc.tag("system-msg"));
+        assert_eq!(
+            Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1)),
+            irc.tmi_sent_ts()
+        );
+        assert_eq!(Some("42"), irc.user_id());
+        assert_eq!(None, irc.display_name());
+    }
+}
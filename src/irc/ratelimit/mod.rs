@@ -0,0 +1,244 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use super::RawMessage;
+
+const CHAT_PERIOD: Duration = Duration::from_secs(30);
+const JOIN_PERIOD: Duration = Duration::from_secs(10);
+const CHANNEL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Twitch chat limits of the account, messages over the limit wait in the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitProfile {
+    /// 20 messages per 30 seconds and 1 per second in each channel
+    #[default]
+    Normal,
+    /// 100 messages per 30 seconds, moderators and VIPs in all the channels
+    Moderator,
+    /// 50 messages per 30 seconds and 1 per second in each channel
+    KnownBot,
+    /// 7500 messages per 30 seconds, 1 per second in each channel and 2000 joins per 10 seconds
+    VerifiedBot,
+    /// No client side limits
+    Unlimited,
+}
+
+impl RateLimitProfile {
+    fn chat_limit(&self) -> Option<u32> {
+        match self {
+            Self::Normal => Some(20),
+            Self::Moderator => Some(100),
+            Self::KnownBot => Some(50),
+            Self::VerifiedBot => Some(7500),
+            Self::Unlimited => None,
+        }
+    }
+
+    fn join_limit(&self) -> Option<u32> {
+        match self {
+            Self::VerifiedBot => Some(2000),
+            Self::Unlimited => None,
+            _ => Some(20),
+        }
+    }
+
+    fn limit_channels(&self) -> bool {
+        !matches!(self, Self::Moderator | Self::Unlimited)
+    }
+}
+
+/// Kind of a command counted by the limiter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) enum Limited {
+    Chat(String),
+    Join,
+}
+
+impl Limited {
+    /// `None` for commands without limits, like PONG
+    pub(super) fn of(command: &str) -> Option<Self> {
+        let raw = RawMessage::parse(command)?;
+        match raw.command {
+            "PRIVMSG" => Some(Self::Chat(raw.channel()?.into())),
+            "JOIN" => Some(Self::Join),
+            _ => None,
+        }
+    }
+}
+
+/// Bucket refilled continuously with `capacity` tokens per `period`
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32, period: Duration, now: Instant) -> Self {
+        Self {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            per_second: capacity as f64 / period.as_secs_f64(),
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until a token is available
+    fn wait(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.per_second)
+        }
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+/// Token buckets for the chat messages and joins of a connection
+pub(super) struct RateLimiter {
+    profile: RateLimitProfile,
+    chat: Option<TokenBucket>,
+    joins: Option<TokenBucket>,
+    last_message: HashMap<String, Instant>,
+    /// Channels where the account is moderator or VIP, without the per-channel limit
+    privileged: HashSet<String>,
+}
+
+impl RateLimiter {
+    pub(super) fn new(profile: RateLimitProfile) -> Self {
+        let now = Instant::now();
+        Self {
+            profile,
+            chat: profile
+                .chat_limit()
+                .map(|limit| TokenBucket::new(limit, CHAT_PERIOD, now)),
+            joins: profile
+                .join_limit()
+                .map(|limit| TokenBucket::new(limit, JOIN_PERIOD, now)),
+            last_message: HashMap::new(),
+            privileged: HashSet::new(),
+        }
+    }
+
+    /// Moderators and VIPs are not limited to a message per second in the channel
+    pub(super) fn set_privileged(&mut self, channel: &str, privileged: bool) {
+        if privileged {
+            self.privileged.insert(channel.into());
+        } else {
+            self.privileged.remove(channel);
+        }
+    }
+
+    fn limit_channel(&self, channel: &str) -> bool {
+        self.profile.limit_channels() && !self.privileged.contains(channel)
+    }
+
+    /// Count the command if there is capacity, otherwise the time to wait
+    pub(super) fn acquire(&mut self, limited: &Limited, now: Instant) -> Result<(), Duration> {
+        match limited {
+            Limited::Join => {
+                let bucket = match &mut self.joins {
+                    Some(bucket) => bucket,
+                    None => return Ok(()),
+                };
+                match bucket.wait(now) {
+                    Duration::ZERO => {
+                        bucket.take();
+                        Ok(())
+                    }
+                    wait => Err(wait),
+                }
+            }
+            Limited::Chat(channel) => {
+                let channel_wait = match self.last_message.get(channel) {
+                    Some(last) if self.limit_channel(channel) => {
+                        CHANNEL_INTERVAL.saturating_sub(now.saturating_duration_since(*last))
+                    }
+                    _ => Duration::ZERO,
+                };
+                let chat_wait = self
+                    .chat
+                    .as_mut()
+                    .map_or(Duration::ZERO, |bucket| bucket.wait(now));
+                let wait = channel_wait.max(chat_wait);
+                if !wait.is_zero() {
+                    return Err(wait);
+                }
+                if let Some(bucket) = &mut self.chat {
+                    bucket.take();
+                }
+                if self.limit_channel(channel) {
+                    self.last_message.insert(channel.clone(), now);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(channel: &str) -> Limited {
+        Limited::of(&format!("PRIVMSG #{channel} :hello\r\n")).unwrap()
+    }
+
+    #[test]
+    fn classify_commands() {
+        assert_eq!(Some(chat("foo")), Limited::of("PRIVMSG #foo :hi\r\n"));
+        assert_eq!(Some(Limited::Join), Limited::of("JOIN #foo\r\n"));
+        assert_eq!(None, Limited::of("PONG :tmi.twitch.tv\r\n"));
+    }
+
+    #[test]
+    fn chat_bucket_and_channel_interval() {
+        let mut limiter = RateLimiter::new(RateLimitProfile::Normal);
+        let now = Instant::now();
+        assert_eq!(Ok(()), limiter.acquire(&chat("foo"), now));
+        assert_eq!(
+            Err(Duration::from_secs(1)),
+            limiter.acquire(&chat("foo"), now)
+        );
+        for idx in 1..20 {
+            assert_eq!(Ok(()), limiter.acquire(&chat(&format!("c{idx}")), now));
+        }
+        let wait = limiter.acquire(&chat("bar"), now).unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(1500));
+        assert_eq!(Ok(()), limiter.acquire(&chat("bar"), now + wait));
+    }
+
+    #[test]
+    fn moderator_and_joins() {
+        let mut limiter = RateLimiter::new(RateLimitProfile::Moderator);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(Ok(()), limiter.acquire(&chat("foo"), now));
+        }
+        assert!(limiter.acquire(&chat("foo"), now).is_err());
+        for _ in 0..20 {
+            assert_eq!(Ok(()), limiter.acquire(&Limited::Join, now));
+        }
+        assert!(limiter.acquire(&Limited::Join, now).is_err());
+
+        let mut limiter = RateLimiter::new(RateLimitProfile::Unlimited);
+        for _ in 0..10_000 {
+            assert_eq!(Ok(()), limiter.acquire(&chat("foo"), now));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{Error as IOError, ErrorKind, Read, Result as IOResult};
+
+const CHUNK_SIZE: usize = 1024;
+/// Longer lines are discarded, Twitch lines are a few KiB at most
+pub(super) const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Buffered reader that frames the incoming bytes into IRC lines,
+/// bytes after the last line terminator are kept for the next call
+#[derive(Default)]
+pub(super) struct LineReader {
+    buffer: Vec<u8>,
+    /// Skipping the rest of a line over `MAX_LINE_LEN`
+    discarding: bool,
+}
+
+impl LineReader {
+    /// Returns the next line without the `\r\n` terminator or `None` on EOF,
+    /// a line over `MAX_LINE_LEN` is an `InvalidData` error and it is skipped
+    pub(super) fn read_line<R: Read>(&mut self, source: &mut R) -> IOResult<Option<Vec<u8>>> {
+        loop {
+            if let Some(line) = self.take_line() {
+                return Ok(Some(line));
+            }
+            let mut chunk = [0; CHUNK_SIZE];
+            match source.read(&mut chunk) {
+                Ok(0) => return Ok(self.take_rest()),
+                Ok(len) => self.push(&chunk[..len])?,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Append bytes read from the source, `InvalidData` when the unterminated line
+    /// is over `MAX_LINE_LEN`, the rest of it is discarded
+    pub(super) fn push(&mut self, mut bytes: &[u8]) -> IOResult<()> {
+        if self.discarding {
+            match bytes.iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    self.discarding = false;
+                    bytes = &bytes[end + 1..];
+                }
+                None => return Ok(()),
+            }
+        }
+        self.buffer.extend_from_slice(bytes);
+        let start = self
+            .buffer
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |end| end + 1);
+        if self.buffer.len() - start > MAX_LINE_LEN {
+            self.buffer.truncate(start);
+            self.discarding = true;
+            return Err(IOError::new(
+                ErrorKind::InvalidData,
+                format!("line longer than {MAX_LINE_LEN} bytes"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bytes of an unterminated last line, when the source reached EOF
+    pub(super) fn take_rest(&mut self) -> Option<Vec<u8>> {
+        (!self.buffer.is_empty()).then(|| std::mem::take(&mut self.buffer))
+    }
+
+    pub(super) fn take_line(&mut self) -> Option<Vec<u8>> {
+        let end = self.buffer.iter().position(|&byte| byte == b'\n')?;
+        let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    struct Chunks(VecDeque<&'static [u8]>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+            match self.0.pop_front() {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(chunk.len())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn frame_lines() {
+        let mut source = Chunks(VecDeque::from([
+            &b"PING :tmi.twitch.tv\r\nPRIVMSG #test :he"[..],
+            &b"llo\r\n:tmi.twitch.tv RECONNECT\r\n"[..],
+            &b"PART #test"[..],
+        ]));
+        let mut reader = LineReader::default();
+        let expected: [&[u8]; 4] = [
+            b"PING :tmi.twitch.tv",
+            b"PRIVMSG #test :hello",
+            b":tmi.twitch.tv RECONNECT",
+            b"PART #test",
+        ];
+        for line in expected {
+            assert_eq!(Some(line.to_vec()), reader.read_line(&mut source).unwrap());
+        }
+        assert_eq!(None, reader.read_line(&mut source).unwrap());
+    }
+
+    #[test]
+    fn discard_long_lines() {
+        let long = vec![b'a'; MAX_LINE_LEN + 1];
+        let mut reader = LineReader::default();
+        let err = reader.push(&long).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        reader.push(b"aaaa\r\nPING :tmi.twitch.tv\r\n").unwrap();
+        assert_eq!(Some(b"PING :tmi.twitch.tv".to_vec()), reader.take_line());
+        assert_eq!(None, reader.take_rest());
+    }
+}
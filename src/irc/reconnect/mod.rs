@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use super::random;
+
+const DEFAULT_ATTEMPTS: usize = 3;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How many times and how long to wait between connection attempts,
+/// used in the first connection and when the connection is lost
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    max_attempts: usize,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    auto_reconnect: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: true,
+            auto_reconnect: true,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Default policy with `max_attempts` connection attempts
+    pub fn new(max_attempts: usize) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// Only one connection attempt and no reconnection when the connection is lost
+    pub fn none() -> Self {
+        Self::new(1).with_auto_reconnect(false)
+    }
+
+    /// Delay before the second attempt, doubled in each attempt
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Upper bound of the delay between attempts
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Randomize the delay between half and the full backoff
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Reconnect when the socket drops or Twitch sends RECONNECT
+    pub fn with_auto_reconnect(mut self, auto_reconnect: bool) -> Self {
+        self.auto_reconnect = auto_reconnect;
+        self
+    }
+
+    pub(super) fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    pub(super) fn auto_reconnect(&self) -> bool {
+        self.auto_reconnect
+    }
+
+    /// Delay after the failed `attempt`, starting from zero
+    pub(super) fn delay(&self, attempt: usize) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.min(u32::MAX as usize) as u32);
+        let backoff = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        if !self.jitter {
+            return backoff;
+        }
+        let half = backoff / 2;
+        let spread = (backoff - half).as_millis() as u64;
+        half + Duration::from_millis(random() % (spread + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = ReconnectPolicy::default()
+            .with_jitter(false)
+            .with_max_delay(Duration::from_secs(5));
+        let delays = (0..5)
+            .map(|attempt| policy.delay(attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![1, 2, 4, 5, 5],
+            delays.iter().map(Duration::as_secs).collect::<Vec<_>>()
+        );
+        assert_eq!(Duration::from_secs(5), policy.delay(usize::MAX));
+    }
+
+    #[test]
+    fn jitter_in_bounds() {
+        let policy = ReconnectPolicy::default();
+        for attempt in 0..8 {
+            let backoff = policy.clone().with_jitter(false).delay(attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay >= backoff / 2 && delay <= backoff);
+        }
+    }
+}
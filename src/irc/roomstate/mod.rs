@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use super::Irc;
+
+/// Chat settings of a channel from ROOMSTATE, a setting is `None` until the server
+/// sends it, an update only has the settings that changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomState {
+    pub room_id: Option<String>,
+    pub emote_only: Option<bool>,
+    pub followers_only: Option<FollowersOnly>,
+    /// Unique chat, messages must be different from the recent ones
+    pub r9k: Option<bool>,
+    /// Time between messages of a user, zero when slow mode is off
+    pub slow: Option<Duration>,
+    pub subs_only: Option<bool>,
+}
+
+/// Followers only mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowersOnly {
+    Disabled,
+    /// Only users following the channel for at least `min_follow` can chat
+    Enabled {
+        min_follow: Duration,
+    },
+}
+
+impl RoomState {
+    pub(super) fn from_irc(irc: &Irc) -> Self {
+        let flag = |key| irc.tag(key).map(|value| value == "1");
+        Self {
+            room_id: irc.room_id().map(str::to_owned),
+            emote_only: flag("emote-only"),
+            followers_only: irc
+                .tag("followers-only")
+                .and_then(|minutes| minutes.parse::<i64>().ok())
+                .map(|minutes| match u64::try_from(minutes) {
+                    Ok(minutes) => FollowersOnly::Enabled {
+                        min_follow: Duration::from_secs(minutes * 60),
+                    },
+                    Err(_) => FollowersOnly::Disabled,
+                }),
+            r9k: flag("r9k"),
+            slow: irc
+                .tag("slow")
+                .and_then(|seconds| seconds.parse().ok())
+                .map(Duration::from_secs),
+            subs_only: flag("subs-only"),
+        }
+    }
+
+    /// Apply the settings sent in `update`
+    pub(super) fn merge(&mut self, update: RoomState) {
+        self.room_id = update.room_id.or(self.room_id.take());
+        self.emote_only = update.emote_only.or(self.emote_only);
+        self.followers_only = update.followers_only.or(self.followers_only);
+        self.r9k = update.r9k.or(self.r9k);
+        self.slow = update.slow.or(self.slow);
+        self.subs_only = update.subs_only.or(self.subs_only);
+    }
+
+    /// Slow mode is on, the time between messages is `slow`
+    pub fn is_slow(&self) -> bool {
+        self.slow.is_some_and(|slow| !slow.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::parser::Parser;
+
+    fn room_state(line: &str) -> RoomState {
+        RoomState::from_irc(&Parser.parse(line.into()).unwrap())
+    }
+
+    #[test]
+    fn merge_updates() {
+        let mut state = room_state(
+            "@emote-only=0;followers-only=-1;r9k=0;room-id=1;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #foo",
+        );
+        assert_eq!(Some(FollowersOnly::Disabled), state.followers_only);
+        assert!(!state.is_slow());
+
+        state.merge(room_state(
+            "@room-id=1;slow=30 :tmi.twitch.tv ROOMSTATE #foo",
+        ));
+        state.merge(room_state(
+            "@followers-only=10;room-id=1 :tmi.twitch.tv ROOMSTATE #foo",
+        ));
+        assert_eq!(
+            RoomState {
+                room_id: Some("1".into()),
+                emote_only: Some(false),
+                followers_only: Some(FollowersOnly::Enabled {
+                    min_follow: Duration::from_secs(600)
+                }),
+                r9k: Some(false),
+                slow: Some(Duration::from_secs(30)),
+                subs_only: Some(false),
+            },
+            state
+        );
+        assert!(state.is_slow());
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Cloneable handle to stop a connection from another thread or a signal handler,
+/// the connection parts the channels, sends QUIT and closes in the next read
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+}
+
+/// How `LocoConnection::read` finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStatus {
+    /// Stopped by a `ShutdownHandle`
+    Shutdown,
+    /// The server closed the connection
+    Closed,
+}
+
+impl ShutdownHandle {
+    /// Request the shutdown, only sets a flag so it is safe in signal handlers
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{Read, Write};
+
+use super::{
+    Capabilities, Events, GlobalUserState, Irc, IrcError, LocoConnection, LocoWriter, ReadStatus,
+    RoomState, ShutdownHandle, UserState, Welcome,
+};
+
+/// Reading half of a `LocoConnection` returned by `split`, it only reads the events,
+/// commands are sent with the `LocoWriter` and written between reads
+pub struct LocoReader<T>
+where
+    T: Read + Write + Unpin,
+{
+    pub(super) connection: LocoConnection<T>,
+}
+
+impl<T> LocoReader<T>
+where
+    T: Read + Write + Unpin,
+{
+    /// See `LocoConnection::next_irc`
+    pub fn next_irc(&mut self) -> Result<Option<Irc>, IrcError> {
+        self.connection.next_irc()
+    }
+
+    /// See `LocoConnection::events`
+    pub fn events(&mut self) -> Events<'_, T> {
+        self.connection.events()
+    }
+
+    /// See `LocoConnection::read`
+    pub fn read(&mut self, exec: impl FnMut(Irc)) -> Result<ReadStatus, IrcError> {
+        self.connection.read(exec)
+    }
+
+    /// Another writer of this connection
+    pub fn writer(&self) -> LocoWriter {
+        self.connection.writer()
+    }
+
+    /// Handle to stop the connection from another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.connection.shutdown_handle()
+    }
+
+    /// See `LocoConnection::shutdown`
+    pub fn shutdown(&mut self) -> Result<(), IrcError> {
+        self.connection.shutdown()
+    }
+
+    pub fn welcome(&self) -> Option<&Welcome> {
+        self.connection.welcome()
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        self.connection.capabilities()
+    }
+
+    /// Channels currently joined, confirmed by the server, without `#`
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.connection.channels()
+    }
+
+    pub fn room_state(&self, channel: &str) -> Option<&RoomState> {
+        self.connection.room_state(channel)
+    }
+
+    pub fn global_user_state(&self) -> Option<&GlobalUserState> {
+        self.connection.global_user_state()
+    }
+
+    pub fn user_state(&self, channel: &str) -> Option<&UserState> {
+        self.connection.user_state(channel)
+    }
+
+    pub fn is_moderator(&self, channel: &str) -> bool {
+        self.connection.is_moderator(channel)
+    }
+
+    /// Number of commands waiting to be written
+    pub fn queue_depth(&self) -> usize {
+        self.connection.queue_depth()
+    }
+}
+
+impl<T> Iterator for LocoReader<T>
+where
+    T: Read + Write + Unpin,
+{
+    type Item = Irc;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.connection.next()
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    borrow::Cow,
+    ops::RangeInclusive,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::Irc;
+
+/// Chat badge as `name/version`, like `subscriber/12`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    pub name: String,
+    pub version: String,
+}
+
+/// RGB color of a user name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Emote used in a message and the char ranges where it appears
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emote {
+    pub id: String,
+    pub ranges: Vec<RangeInclusive<usize>>,
+}
+
+/// Unescape an IRCv3 tag value
+pub(super) fn unescape(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+impl Badge {
+    fn parse_list(value: &str) -> Vec<Self> {
+        value
+            .split(',')
+            .filter_map(|badge| badge.split_once('/'))
+            .map(|(name, version)| Self {
+                name: name.into(),
+                version: version.into(),
+            })
+            .collect()
+    }
+}
+
+impl Color {
+    fn parse(value: &str) -> Option<Self> {
+        let hex = value.strip_prefix('#')?;
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |idx: usize| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok();
+        Some(Self {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+impl Emote {
+    fn parse_list(value: &str) -> Vec<Self> {
+        value
+            .split('/')
+            .filter_map(|emote| emote.split_once(':'))
+            .map(|(id, ranges)| Self {
+                id: id.into(),
+                ranges: ranges
+                    .split(',')
+                    .filter_map(|range| range.split_once('-'))
+                    .filter_map(|(start, end)| Some(start.parse().ok()?..=end.parse().ok()?))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+impl Irc {
+    /// Raw value of a tag
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.keys.as_ref()?.get(key).map(String::as_str)
+    }
+
+    fn flag(&self, key: &str) -> bool {
+        self.tag(key) == Some("1")
+    }
+
+    /// Badges from `badges` tag
+    pub fn badges(&self) -> Vec<Badge> {
+        self.tag("badges")
+            .map(Badge::parse_list)
+            .unwrap_or_default()
+    }
+
+    /// Badges extra info from `badge-info` tag, like subscribed months
+    pub fn badge_info(&self) -> Vec<Badge> {
+        self.tag("badge-info")
+            .map(Badge::parse_list)
+            .unwrap_or_default()
+    }
+
+    /// Name color, `None` if the user never set one
+    pub fn color(&self) -> Option<Color> {
+        self.tag("color").and_then(Color::parse)
+    }
+
+    /// Emotes from `emotes` tag
+    pub fn emotes(&self) -> Vec<Emote> {
+        self.tag("emotes")
+            .map(Emote::parse_list)
+            .unwrap_or_default()
+    }
+
+    pub fn display_name(&self) -> Option<&str> {
+        self.tag("display-name").filter(|name| !name.is_empty())
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        self.tag("user-id")
+    }
+
+    pub fn room_id(&self) -> Option<&str> {
+        self.tag("room-id")
+    }
+
+    /// Id of the message from `id` tag
+    pub fn message_id(&self) -> Option<&str> {
+        self.tag("id")
+    }
+
+    pub fn is_mod(&self) -> bool {
+        self.flag("mod")
+    }
+
+    pub fn is_subscriber(&self) -> bool {
+        self.flag("subscriber")
+    }
+
+    /// Amount of cheered bits
+    pub fn bits(&self) -> Option<u64> {
+        self.tag("bits")?.parse().ok()
+    }
+
+    /// Time the server sent the message
+    pub fn tmi_sent_ts(&self) -> Option<SystemTime> {
+        let millis = self.tag("tmi-sent-ts")?.parse().ok()?;
+        Some(UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_values() {
+        let inputs = [
+            ("plain", "plain"),
+            (r"hello\sworld", "hello world"),
+            (r"a\:b", "a;b"),
+            (r"back\\slash", r"back\slash"),
+            (r"line\r\n", "line\r\n"),
+            (r"unknown\x", "unknownx"),
+            (r"dangling\", "dangling"),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(expected, unescape(input))
+        }
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(
+            vec![
+                Badge {
+                    name: "broadcaster".into(),
+                    version: "1".into()
+                },
+                Badge {
+                    name: "subscriber".into(),
+                    version: "12".into()
+                }
+            ],
+            Badge::parse_list("broadcaster/1,subscriber/12")
+        );
+        assert!(Badge::parse_list("").is_empty());
+        assert_eq!(
+            Some(Color {
+                r: 0x1E,
+                g: 0x90,
+                b: 0xFF
+            }),
+            Color::parse("#1E90FF")
+        );
+        assert_eq!(None, Color::parse(""));
+        assert_eq!(
+            vec![
+                Emote {
+                    id: "25".into(),
+                    ranges: vec![0..=4, 12..=16]
+                },
+                Emote {
+                    id: "1902".into(),
+                    ranges: vec![6..=10]
+                }
+            ],
+            Emote::parse_list("25:0-4,12-16/1902:6-10")
+        );
+    }
+}
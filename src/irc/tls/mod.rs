@@ -0,0 +1,129 @@
+use std::{
+    io::{Error as IOError, ErrorKind, Result as IOResult},
+    net::TcpStream,
+    sync::Arc,
+};
+
+use rustls::{
+    crypto::ring, pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+
+use super::{IrcError, LocoConfig, LocoConnection, POLL_INTERVAL};
+
+const IRC_TLS_PORT: u16 = 6697;
+
+/// TLS stream used by `LocoConnection::new_tls`
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+impl LocoConnection<TlsStream> {
+    /// Initialize a TLS Connection
+    pub fn new_tls(loco_config: LocoConfig) -> Result<LocoConnection<TlsStream>, IrcError> {
+        let mut con = LocoConnection::from_stream(None, loco_config);
+        con.connector = Some(Box::new(Self::open));
+        con.establish()?;
+        Ok(con)
+    }
+
+    fn open(loco_config: &LocoConfig) -> IOResult<TlsStream> {
+        let mut roots = RootCertStore::empty();
+        if loco_config.root_certificates.is_empty() {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        } else {
+            for certificate in &loco_config.root_certificates {
+                roots
+                    .add(certificate.clone())
+                    .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+            }
+        }
+        let tls_config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(loco_config.host.as_str())
+            .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+        let mut tls = ClientConnection::new(Arc::new(tls_config), server_name.to_owned())
+            .map_err(|err| IOError::new(ErrorKind::InvalidData, err))?;
+        let mut connection = loco_config.open_tcp(IRC_TLS_PORT)?;
+        // the handshake can take longer than the poll interval of the reads
+        connection.set_read_timeout(Some(
+            loco_config
+                .connect_timeout
+                .unwrap_or(loco_config.login_timeout),
+        ))?;
+        while tls.is_handshaking() {
+            tls.complete_io(&mut connection)?;
+        }
+        connection.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(StreamOwned::new(tls, connection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+        time::Duration,
+    };
+
+    use rustls::{pki_types::PrivateKeyDer, ServerConfig, ServerConnection};
+
+    use super::*;
+    use crate::irc::{IrcType, ReconnectPolicy};
+
+    #[test]
+    fn slow_handshake_with_local_server() {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let certificate = certified.cert.der().clone();
+        let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![certificate.clone()],
+                PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into()),
+            )
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            // longer than the poll interval of the client reads
+            thread::sleep(Duration::from_millis(400));
+            let tls = ServerConnection::new(Arc::new(server_config)).unwrap();
+            let mut stream = BufReader::new(StreamOwned::new(tls, tcp));
+            let mut login = Vec::new();
+            while !login.iter().any(|line: &String| line.starts_with("NICK")) {
+                let mut line = String::new();
+                stream.read_line(&mut line).unwrap();
+                login.push(line);
+            }
+            stream
+                .get_mut()
+                .write_all(
+                    b":tmi.twitch.tv 001 nick :Welcome, GLHF!\r\n:foo!foo@foo.tmi.twitch.tv PRIVMSG #chan :hello\r\n",
+                )
+                .unwrap();
+            (stream, login)
+        });
+        let config = LocoConfig::new("token".into(), "nick".into(), "chan".into())
+            .with_host("localhost".into())
+            .with_port(port)
+            .with_connect_timeout(Duration::from_secs(2))
+            .with_root_certificates(vec![certificate])
+            .with_reconnect(ReconnectPolicy::none());
+        let mut conn = LocoConnection::new_tls(config).unwrap();
+        assert_eq!("nick", conn.welcome().unwrap().nickname);
+        assert!(matches!(
+            conn.next_irc().unwrap().unwrap().irc_type,
+            IrcType::Unknown
+        ));
+        let irc = conn.next_irc().unwrap().unwrap();
+        assert!(matches!(irc.irc_type, IrcType::Message));
+        assert_eq!(Some("hello"), irc.message.as_deref());
+        let (_stream, login) = server.join().unwrap();
+        assert_eq!("PASS oauth:token\r\n", login[3]);
+    }
+}
@@ -0,0 +1,284 @@
+use super::Irc;
+
+/// USERNOTICE decoded by its `msg-id` with the `msg-param-*` tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserNoticeKind {
+    Sub(Subscription),
+    Resub(Subscription),
+    SubGift(SubGift),
+    /// Gift from an anonymous user
+    AnonSubGift(SubGift),
+    /// Many subscriptions gifted at once, followed by a `SubGift` for each one
+    SubMysteryGift {
+        count: u32,
+        /// Total gifted by the user in the channel, hidden by some users
+        sender_count: Option<u32>,
+        plan: SubPlan,
+    },
+    /// Gifted subscription continued by the user
+    GiftPaidUpgrade {
+        /// Login of the gifter, `None` when it was anonymous
+        sender_login: Option<String>,
+        sender_name: Option<String>,
+    },
+    /// Prime subscription converted to a paid one
+    PrimePaidUpgrade {
+        plan: SubPlan,
+    },
+    Raid {
+        /// Login of the raiding channel
+        login: String,
+        display_name: String,
+        viewer_count: u32,
+    },
+    Unraid,
+    /// Like the first message of a new chatter, `new_chatter`
+    Ritual {
+        name: String,
+    },
+    /// New tier of bits badge, the threshold is the amount of bits
+    BitsBadgeTier {
+        threshold: u64,
+    },
+    Announcement {
+        color: AnnouncementColor,
+    },
+    /// Like a watch streak
+    ViewerMilestone {
+        category: String,
+        value: u32,
+        /// Channel points rewarded
+        reward: Option<u32>,
+    },
+    /// Other `msg-id`
+    Other(String),
+}
+
+/// Subscription or resubscription
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    pub cumulative_months: u32,
+    /// Consecutive months, only when the user shares it
+    pub streak_months: Option<u32>,
+    pub plan: SubPlan,
+    pub plan_name: Option<String>,
+}
+
+/// Subscription gifted to another user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubGift {
+    pub recipient_login: String,
+    pub recipient_display_name: String,
+    pub recipient_id: Option<String>,
+    /// Cumulative months of the recipient
+    pub months: u32,
+    /// Months gifted at once
+    pub gift_months: u32,
+    pub plan: SubPlan,
+    pub plan_name: Option<String>,
+}
+
+/// Subscription tier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubPlan {
+    Prime,
+    Tier1,
+    Tier2,
+    Tier3,
+    Other(String),
+}
+
+/// Highlight color of an announcement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnnouncementColor {
+    Primary,
+    Blue,
+    Green,
+    Orange,
+    Purple,
+    Other(String),
+}
+
+impl UserNoticeKind {
+    pub(super) fn from_irc(irc: &Irc) -> Self {
+        let params = Params(irc);
+        match irc.tag("msg-id").unwrap_or_default() {
+            "sub" => Self::Sub(Subscription::from_params(&params)),
+            "resub" => Self::Resub(Subscription::from_params(&params)),
+            "subgift" => Self::SubGift(SubGift::from_params(&params)),
+            "anonsubgift" => Self::AnonSubGift(SubGift::from_params(&params)),
+            "submysterygift" => Self::SubMysteryGift {
+                count: params.number("mass-gift-count").unwrap_or_default(),
+                sender_count: params.number("sender-count").filter(|count| *count > 0),
+                plan: params.plan(),
+            },
+            "giftpaidupgrade" | "anongiftpaidupgrade" => Self::GiftPaidUpgrade {
+                sender_login: params.text("sender-login"),
+                sender_name: params.text("sender-name"),
+            },
+            "primepaidupgrade" => Self::PrimePaidUpgrade {
+                plan: params.plan(),
+            },
+            "raid" => Self::Raid {
+                login: params.text("login").unwrap_or_default(),
+                display_name: params.text("displayName").unwrap_or_default(),
+                viewer_count: params.number("viewerCount").unwrap_or_default(),
+            },
+            "unraid" => Self::Unraid,
+            "ritual" => Self::Ritual {
+                name: params.text("ritual-name").unwrap_or_default(),
+            },
+            "bitsbadgetier" => Self::BitsBadgeTier {
+                threshold: params.number("threshold").unwrap_or_default(),
+            },
+            "announcement" => Self::Announcement {
+                color: AnnouncementColor::parse(params.0.tag("msg-param-color")),
+            },
+            "viewermilestone" => Self::ViewerMilestone {
+                category: params.text("category").unwrap_or_default(),
+                value: params.number("value").unwrap_or_default(),
+                reward: params.number("copoReward"),
+            },
+            other => Self::Other(other.into()),
+        }
+    }
+}
+
+impl Subscription {
+    fn from_params(params: &Params) -> Self {
+        let shared = params.0.tag("msg-param-should-share-streak") == Some("1");
+        Self {
+            cumulative_months: params.number("cumulative-months").unwrap_or_default(),
+            streak_months: params.number("streak-months").filter(|_| shared),
+            plan: params.plan(),
+            plan_name: params.text("sub-plan-name"),
+        }
+    }
+}
+
+impl SubGift {
+    fn from_params(params: &Params) -> Self {
+        Self {
+            recipient_login: params.text("recipient-user-name").unwrap_or_default(),
+            recipient_display_name: params.text("recipient-display-name").unwrap_or_default(),
+            recipient_id: params.text("recipient-id"),
+            months: params.number("months").unwrap_or_default(),
+            gift_months: params.number("gift-months").unwrap_or(1),
+            plan: params.plan(),
+            plan_name: params.text("sub-plan-name"),
+        }
+    }
+}
+
+impl SubPlan {
+    fn parse(plan: &str) -> Self {
+        match plan {
+            "Prime" => Self::Prime,
+            "1000" => Self::Tier1,
+            "2000" => Self::Tier2,
+            "3000" => Self::Tier3,
+            other => Self::Other(other.into()),
+        }
+    }
+}
+
+impl AnnouncementColor {
+    fn parse(color: Option<&str>) -> Self {
+        match color.unwrap_or("PRIMARY") {
+            "PRIMARY" => Self::Primary,
+            "BLUE" => Self::Blue,
+            "GREEN" => Self::Green,
+            "ORANGE" => Self::Orange,
+            "PURPLE" => Self::Purple,
+            other => Self::Other(other.into()),
+        }
+    }
+}
+
+/// The `msg-param-*` tags of a USERNOTICE
+struct Params<'a>(&'a Irc);
+
+impl Params<'_> {
+    fn text(&self, name: &str) -> Option<String> {
+        self.0
+            .tag(&format!("msg-param-{name}"))
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+    }
+
+    fn number<N: std::str::FromStr>(&self, name: &str) -> Option<N> {
+        self.0.tag(&format!("msg-param-{name}"))?.parse().ok()
+    }
+
+    fn plan(&self) -> SubPlan {
+        SubPlan::parse(&self.text("sub-plan").unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::parser::Parser;
+
+    fn kind(tags: &str) -> UserNoticeKind {
+        let line = format!("@{tags} :tmi.twitch.tv USERNOTICE #foo :hello");
+        UserNoticeKind::from_irc(&Parser.parse(line).unwrap())
+    }
+
+    #[test]
+    fn decode_user_notices() {
+        assert_eq!(
+            UserNoticeKind::Resub(Subscription {
+                cumulative_months: 12,
+                streak_months: Some(3),
+                plan: SubPlan::Tier2,
+                plan_name: Some("Tier 2 sub".into()),
+            }),
+            kind(concat!(
+                r"msg-id=resub;msg-param-cumulative-months=12;msg-param-should-share-streak=1;",
+                r"msg-param-streak-months=3;msg-param-sub-plan=2000;msg-param-sub-plan-name=Tier\s2\ssub"
+            ))
+        );
+        let UserNoticeKind::AnonSubGift(gift) = kind(concat!(
+            "msg-id=anonsubgift;msg-param-months=5;msg-param-recipient-display-name=Bar;",
+            "msg-param-recipient-id=7;msg-param-recipient-user-name=bar;msg-param-sub-plan=Prime"
+        )) else {
+            panic!("expected anonsubgift");
+        };
+        assert_eq!(
+            ("bar", 5, 1, SubPlan::Prime),
+            (
+                gift.recipient_login.as_str(),
+                gift.months,
+                gift.gift_months,
+                gift.plan
+            )
+        );
+        assert_eq!(
+            UserNoticeKind::Raid {
+                login: "bar".into(),
+                display_name: "Bar".into(),
+                viewer_count: 42,
+            },
+            kind("msg-id=raid;msg-param-displayName=Bar;msg-param-login=bar;msg-param-viewerCount=42")
+        );
+        assert_eq!(
+            UserNoticeKind::Announcement {
+                color: AnnouncementColor::Purple
+            },
+            kind("msg-id=announcement;msg-param-color=PURPLE")
+        );
+        assert_eq!(
+            UserNoticeKind::SubMysteryGift {
+                count: 5,
+                sender_count: None,
+                plan: SubPlan::Tier1,
+            },
+            kind("msg-id=submysterygift;msg-param-mass-gift-count=5;msg-param-sender-count=0;msg-param-sub-plan=1000")
+        );
+        assert_eq!(
+            UserNoticeKind::Other("charitydonation".into()),
+            kind("msg-id=charitydonation")
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use super::{Badge, Color, Irc};
+
+/// State of the bot in a channel from USERSTATE, sent after joining and after
+/// each message sent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserState {
+    pub display_name: Option<String>,
+    pub badges: Vec<Badge>,
+    pub color: Option<Color>,
+    /// Ids of the emote sets the bot can use
+    pub emote_sets: Vec<String>,
+    pub is_mod: bool,
+    pub is_vip: bool,
+    pub is_subscriber: bool,
+    pub is_broadcaster: bool,
+}
+
+/// Identity of the bot from GLOBALUSERSTATE, sent after logging in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlobalUserState {
+    pub user_id: Option<String>,
+    pub display_name: Option<String>,
+    pub badges: Vec<Badge>,
+    pub color: Option<Color>,
+    pub emote_sets: Vec<String>,
+}
+
+impl UserState {
+    pub(super) fn from_irc(irc: &Irc) -> Self {
+        let badges = irc.badges();
+        let has_badge = |name| badges.iter().any(|badge| badge.name == name);
+        Self {
+            display_name: irc.display_name().map(str::to_owned),
+            color: irc.color(),
+            emote_sets: emote_sets(irc),
+            is_mod: irc.is_mod(),
+            is_vip: irc.tag("vip").is_some() || has_badge("vip"),
+            is_subscriber: irc.is_subscriber(),
+            is_broadcaster: has_badge("broadcaster"),
+            badges,
+        }
+    }
+
+    /// Moderator or broadcaster, can use moderation commands and chat without
+    /// the per-channel limit
+    pub fn is_moderator(&self) -> bool {
+        self.is_mod || self.is_broadcaster
+    }
+}
+
+impl GlobalUserState {
+    pub(super) fn from_irc(irc: &Irc) -> Self {
+        Self {
+            user_id: irc.user_id().map(str::to_owned),
+            display_name: irc.display_name().map(str::to_owned),
+            badges: irc.badges(),
+            color: irc.color(),
+            emote_sets: emote_sets(irc),
+        }
+    }
+}
+
+fn emote_sets(irc: &Irc) -> Vec<String> {
+    irc.tag("emote-sets")
+        .into_iter()
+        .flat_map(|sets| sets.split(','))
+        .filter(|set| !set.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc::parser::Parser;
+
+    #[test]
+    fn parse_user_states() {
+        let irc = Parser
+            .parse(
+                "@badges=vip/1;color=#FF0000;display-name=Nick;emote-sets=0,33;mod=0;subscriber=1;vip=1 :tmi.twitch.tv USERSTATE #foo"
+                    .into(),
+            )
+            .unwrap();
+        let state = UserState::from_irc(&irc);
+        assert_eq!(vec!["0", "33"], state.emote_sets);
+        assert!(state.is_vip && state.is_subscriber);
+        assert!(!state.is_moderator());
+
+        let irc = Parser
+            .parse("@badges=broadcaster/1;mod=0 :tmi.twitch.tv USERSTATE #nick".into())
+            .unwrap();
+        assert!(UserState::from_irc(&irc).is_moderator());
+
+        let irc = Parser
+            .parse(
+                "@badges=;color=;display-name=Nick;emote-sets=0;user-id=42;user-type= :tmi.twitch.tv GLOBALUSERSTATE"
+                    .into(),
+            )
+            .unwrap();
+        let state = GlobalUserState::from_irc(&irc);
+        assert_eq!(Some("42".into()), state.user_id);
+        assert_eq!(None, state.color);
+        assert!(state.badges.is_empty());
+    }
+}
@@ -0,0 +1,22 @@
+use super::RawMessage;
+
+/// Welcome sent by the server after a successful login, the `001` reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Welcome {
+    /// Nickname accepted by the server
+    pub nickname: String,
+    /// Server that sent the welcome, usually `tmi.twitch.tv`
+    pub server: Option<String>,
+    /// Welcome text
+    pub message: String,
+}
+
+impl Welcome {
+    pub(super) fn from_raw(raw: &RawMessage) -> Self {
+        Self {
+            nickname: raw.params.first().copied().unwrap_or_default().into(),
+            server: raw.nick().map(str::to_owned),
+            message: raw.trailing.unwrap_or_default().into(),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use super::{check_text, checked_channel, outbox::Outbox, Command, IrcError, LocoConfig};
+
+/// Cloneable handle to send commands while the connection is read in another thread,
+/// the commands are written by the connection between reads.
+/// When the queue is full it waits, discards or fails following `FullQueue`
+#[derive(Clone)]
+pub struct LocoWriter {
+    pub(super) outbox: Outbox,
+    pub(super) config: Arc<LocoConfig>,
+    /// Wait for space in a full queue, unset for the reading connection
+    pub(super) blocking: bool,
+}
+
+impl LocoWriter {
+    /// Send a command to IRC, chat messages fail with `IrcError::Anonymous`
+    /// in anonymous connections, `arg` can not have line breaks
+    pub fn send_command(&self, command: Command, arg: &str) -> Result<(), IrcError> {
+        check_text(arg)?;
+        let arg = match command {
+            Command::Join | Command::Part => checked_channel(arg)?,
+            Command::Privmsg => {
+                self.check_can_chat()?;
+                arg.into()
+            }
+            _ => arg.into(),
+        };
+        self.outbox
+            .push(command.build(arg, &self.config), self.blocking)
+    }
+
+    /// Join a channel
+    pub fn join(&self, channel: &str) -> Result<(), IrcError> {
+        self.send_command(Command::Join, &checked_channel(channel)?)
+    }
+
+    /// Leave a channel
+    pub fn part(&self, channel: &str) -> Result<(), IrcError> {
+        self.send_command(Command::Part, &checked_channel(channel)?)
+    }
+
+    /// Send a chat message to a channel, the text can not have line breaks
+    pub fn privmsg(&self, channel: &str, text: &str) -> Result<(), IrcError> {
+        self.check_can_chat()?;
+        let channel = checked_channel(channel)?;
+        check_text(text)?;
+        self.outbox
+            .push(format!("PRIVMSG #{channel} :{text}\r\n"), self.blocking)
+    }
+
+    /// Number of commands waiting to be written
+    pub fn queue_depth(&self) -> usize {
+        self.outbox.depth()
+    }
+
+    fn check_can_chat(&self) -> Result<(), IrcError> {
+        if self.config.anonymous {
+            return Err(IrcError::Anonymous);
+        }
+        Ok(())
+    }
+}